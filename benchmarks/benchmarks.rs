@@ -12,10 +12,10 @@ use test::Bencher;
 #[bench]
 fn render_frame(b: &mut Bencher) {
     const ROM_SRC: &[u8] = include_bytes!("../roms/Testing/NEStress.NES");
-    let mut rom = RomFile::from_bytes(ROM_SRC);
+    let rom = RomFile::from_bytes(ROM_SRC).unwrap();
     let mut nes = NesConsole::new();
 
-    nes.bus.borrow_mut().connect_cartridge(&mut rom);
+    nes.bus.borrow_mut().connect_cartridge(rom).unwrap();
     nes.reset();
 
     b.iter(|| nes.render_full_frame());
@@ -24,10 +24,10 @@ fn render_frame(b: &mut Bencher) {
 #[bench]
 fn nes_speed(b: &mut Bencher) {
     const ROM_SRC: &[u8] = include_bytes!("../roms/Testing/NEStress.NES");
-    let mut rom = RomFile::from_bytes(ROM_SRC);
+    let rom = RomFile::from_bytes(ROM_SRC).unwrap();
     let mut nes = NesConsole::new();
 
-    nes.bus.borrow_mut().connect_cartridge(&mut rom);
+    nes.bus.borrow_mut().connect_cartridge(rom).unwrap();
     nes.reset();
 
     b.iter(|| nes.tick());
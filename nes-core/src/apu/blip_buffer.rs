@@ -0,0 +1,190 @@
+use std::f64::consts::PI;
+
+/// Number of sub-sample positions the kernel is computed for.
+const PHASES: usize = 32;
+/// Width of the kernel in output samples, also the latency of the buffer.
+const WIDTH: usize = 16;
+
+/// Band-limited resampler: instead of point sampling the input signal, every amplitude change
+/// is added to the output as a windowed sinc impulse and integrated back into a step when read,
+/// so frequencies above the output Nyquist limit don't alias back into the audible range.
+/// Based off on the idea behind blargg's Blip_Buffer http://www.slack.net/~ant/libs/audio.html
+pub struct BlipBuffer {
+    /// Output samples per input clock.
+    factor: f64,
+    /// Current position in output samples, relative to the start of `impulses`.
+    time: f64,
+    /// Pending impulses, the first `time` samples are ready to be read.
+    impulses: Vec<f32>,
+    integrator: f32,
+    amplitude: f32,
+    /// Samples kept before the oldest half gets discarded, in case nobody is reading.
+    capacity: usize,
+    kernel: Box<[[f32; WIDTH]; PHASES]>,
+}
+
+impl BlipBuffer {
+    pub fn new(clock_rate: f64, sample_rate: f64) -> Self {
+        let mut buffer = Self {
+            factor: 0.0,
+            time: 0.0,
+            impulses: vec![0.0; WIDTH + 1],
+            integrator: 0.0,
+            amplitude: 0.0,
+            capacity: 0,
+            kernel: Box::new(build_kernel()),
+        };
+        buffer.set_rates(clock_rate, sample_rate);
+        buffer
+    }
+
+    /// Changes the input and output rates, keeping the samples already generated.
+    pub fn set_rates(&mut self, clock_rate: f64, sample_rate: f64) {
+        self.factor = sample_rate / clock_rate;
+        self.capacity = sample_rate as usize; // 1 second of audio
+    }
+
+    /// Advances the input by one clock.
+    pub fn clock(&mut self) {
+        self.time += self.factor;
+
+        // Drop the oldest half at once, discarding sample by sample would move the whole buffer
+        // for every new sample
+        if self.time as usize > self.capacity {
+            self.discard(self.capacity / 2);
+        }
+    }
+
+    /// Sets the input amplitude at the current clock.
+    pub fn set_amplitude(&mut self, amplitude: f32) {
+        let delta = amplitude - self.amplitude;
+        if delta == 0.0 {
+            return;
+        }
+        self.amplitude = amplitude;
+
+        let position = self.time.floor();
+        let phase = ((self.time - position) * PHASES as f64) as usize;
+        let start = position as usize;
+
+        self.reserve();
+        for (i, weight) in self.kernel[phase].iter().enumerate() {
+            self.impulses[start + i] += delta * weight;
+        }
+    }
+
+    /// Number of output samples ready to be read.
+    pub fn samples_available(&self) -> usize {
+        self.time as usize
+    }
+
+    /// Reads as many samples as available into `buf`, returning how many were written.
+    pub fn read_samples(&mut self, buf: &mut [f32]) -> usize {
+        let count = buf.len().min(self.samples_available());
+        self.reserve();
+
+        for (sample, impulse) in buf.iter_mut().zip(self.impulses.iter()).take(count) {
+            self.integrator += impulse;
+            *sample = self.integrator;
+        }
+
+        self.remove(count);
+        count
+    }
+
+    fn discard(&mut self, count: usize) {
+        self.reserve();
+        for impulse in self.impulses.iter().take(count) {
+            self.integrator += impulse;
+        }
+        self.remove(count);
+    }
+
+    fn remove(&mut self, count: usize) {
+        self.impulses.drain(..count);
+        self.time -= count as f64;
+    }
+
+    /// Makes sure there is room for an impulse at the current position.
+    fn reserve(&mut self) {
+        let len = self.time as usize + WIDTH + 1;
+        if self.impulses.len() < len {
+            self.impulses.resize(len, 0.0);
+        }
+    }
+}
+
+/// Windowed sinc low-pass kernel, each phase is normalized so a step settles at its exact height.
+fn build_kernel() -> [[f32; WIDTH]; PHASES] {
+    // Cut off a bit below Nyquist to leave room for the transition band
+    const CUTOFF: f64 = 0.9;
+    let mut kernel = [[0f32; WIDTH]; PHASES];
+
+    for (phase, weights) in kernel.iter_mut().enumerate() {
+        let offset = phase as f64 / PHASES as f64;
+        let mut sum = 0.0;
+        let mut values = [0f64; WIDTH];
+
+        for (i, value) in values.iter_mut().enumerate() {
+            let x = i as f64 - (WIDTH / 2) as f64 - offset + 1.0;
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (PI * x * CUTOFF).sin() / (PI * x * CUTOFF)
+            };
+            // Blackman window
+            let w = (x + (WIDTH / 2) as f64) / WIDTH as f64;
+            let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+            *value = sinc * window.max(0.0);
+            sum += *value;
+        }
+
+        for (weight, value) in weights.iter_mut().zip(values.iter()) {
+            *weight = (value / sum) as f32;
+        }
+    }
+
+    kernel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BlipBuffer;
+
+    #[test]
+    fn resample_rate() {
+        let mut blip = BlipBuffer::new(1_048_576.0, 32_768.0);
+
+        for _ in 0..32_768 {
+            blip.clock();
+        }
+
+        assert_eq!(blip.samples_available(), 1024);
+    }
+
+    #[test]
+    fn discards_when_full() {
+        let mut blip = BlipBuffer::new(1_048_576.0, 32_768.0);
+
+        for _ in 0..32 * 32_769 {
+            blip.clock();
+        }
+
+        assert_eq!(blip.samples_available(), 32_769 - 16_384);
+    }
+
+    #[test]
+    fn step_settles() {
+        let mut blip = BlipBuffer::new(1_048_576.0, 32_768.0);
+        let mut buf = [0f32; 100];
+
+        blip.set_amplitude(0.5);
+        for _ in 0..4096 {
+            blip.clock();
+        }
+
+        assert_eq!(blip.read_samples(&mut buf), 100);
+        assert!(buf[0].abs() < 1e-2);
+        assert!((buf[99] - 0.5).abs() < 1e-4);
+    }
+}
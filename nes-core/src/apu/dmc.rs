@@ -0,0 +1,178 @@
+/// Timer periods in CPU cycles.
+/// https://wiki.nesdev.com/w/index.php/APU_DMC
+const RATE_TABLE_NTSC: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+/// Delta modulation channel at $4010-$4013, plays 1-bit delta encoded samples fetched from
+/// CPU memory through DMA.
+/// https://wiki.nesdev.com/w/index.php/APU_DMC
+pub struct Dmc {
+    irq_enabled: bool,
+    looping: bool,
+    timer: u16,
+    timer_period: u16,
+    /// 7-bit output level
+    output_level: u8,
+
+    // #region Memory reader
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    // #endregion
+
+    // #region Output unit
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+    // #endregion
+    pub interrupt: bool,
+}
+
+impl Dmc {
+    pub fn new() -> Self {
+        Self {
+            irq_enabled: false,
+            looping: false,
+            timer: 0,
+            timer_period: RATE_TABLE_NTSC[0],
+            output_level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            interrupt: false,
+        }
+    }
+
+    /// Writes to one of the four channel registers, `index` is the address offset (0 to 3).
+    pub fn write_register(&mut self, index: u16, value: u8) {
+        match index {
+            0 => {
+                // IL-- RRRR
+                self.irq_enabled = (value & 0x80) != 0;
+                self.looping = (value & 0x40) != 0;
+                self.timer_period = RATE_TABLE_NTSC[(value & 0x0F) as usize];
+                if !self.irq_enabled {
+                    self.interrupt = false;
+                }
+            }
+            1 => self.output_level = value & 0x7F, // -DDD DDDD
+            2 => self.sample_address = 0xC000 | ((value as u16) << 6), // AAAA AAAA
+            3 => self.sample_length = ((value as u16) << 4) | 1, // LLLL LLLL
+            _ => unreachable!(),
+        }
+    }
+
+    /// Handles the D bit of $4015 writes.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Address the memory reader wants to fetch, if the sample buffer needs to be refilled.
+    pub fn dma_request(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    /// Fills the sample buffer with the byte fetched for the last `dma_request`.
+    pub fn dma_complete(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+        self.current_address = if self.current_address == 0xFFFF {
+            0x8000
+        } else {
+            self.current_address + 1
+        };
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.interrupt = true;
+            }
+        }
+    }
+
+    /// Clocked on every CPU cycle.
+    /// https://wiki.nesdev.com/w/index.php/APU_DMC#Output_unit
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if (self.shift_register & 0x01) != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+
+        self.shift_register >>= 1;
+        self.bits_remaining -= 1;
+
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    /// Current output level (0 to 127).
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dmc;
+
+    #[test]
+    fn sample_fetch() {
+        let mut dmc = Dmc::new();
+        dmc.write_register(0, 0x80); // IRQ enabled
+        dmc.write_register(2, 0x01); // $C040
+        dmc.write_register(3, 0x00); // 1 byte
+        dmc.set_enabled(true);
+
+        assert_eq!(dmc.dma_request(), Some(0xC040));
+        dmc.dma_complete(0xFF);
+        assert_eq!(dmc.dma_request(), None);
+        assert!(!dmc.active());
+        assert!(dmc.interrupt);
+    }
+}
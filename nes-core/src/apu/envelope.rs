@@ -0,0 +1,53 @@
+/// Generates either a constant volume or a saw envelope with an optional loop.
+/// https://wiki.nesdev.com/w/index.php/APU_Envelope
+#[derive(Default)]
+pub struct Envelope {
+    start: bool,
+    divider: u8,
+    decay_level: u8,
+    /// Volume (or envelope divider period) from the low 4 bits of the control register
+    volume: u8,
+    constant_volume: bool,
+    /// Also used as the length counter halt flag by the owner channel
+    looping: bool,
+}
+
+impl Envelope {
+    /// Writes the `--LC VVVV` bits of the channel control register.
+    pub fn write_control(&mut self, value: u8) {
+        self.looping = (value & 0x20) != 0;
+        self.constant_volume = (value & 0x10) != 0;
+        self.volume = value & 0x0F;
+    }
+
+    /// Restarts the envelope on the next quarter frame clock.
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    /// Clocked by the frame counter on every quarter frame.
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay_level = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay_level > 0 {
+                self.decay_level -= 1;
+            } else if self.looping {
+                self.decay_level = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.constant_volume {
+            self.volume
+        } else {
+            self.decay_level
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::f32::consts::PI;
+
+/// Stages of the filter chain found between the 2A03 and the audio output of the NES.
+/// https://wiki.nesdev.com/w/index.php/APU_Mixer
+#[derive(Clone, Copy, Debug)]
+pub enum AudioFilter {
+    /// First-order high-pass filter at 90 Hz
+    HighPass90Hz,
+    /// First-order high-pass filter at 440 Hz
+    HighPass440Hz,
+    /// First-order low-pass filter at 14 kHz
+    LowPass14kHz,
+}
+
+/// Cutoff of the DC blocker used in clean mode, low enough to leave the bass untouched.
+const CLEAN_CUTOFF: f32 = 5.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    HighPass,
+    LowPass,
+}
+
+#[derive(Clone)]
+struct Filter {
+    kind: Kind,
+    cutoff: f32,
+    alpha: f32,
+    previous_input: f32,
+    previous_output: f32,
+    enabled: bool,
+}
+
+impl Filter {
+    fn new(kind: Kind, cutoff: f32, sample_rate: u32) -> Self {
+        let mut filter = Self {
+            kind,
+            cutoff,
+            alpha: 0.0,
+            previous_input: 0.0,
+            previous_output: 0.0,
+            enabled: true,
+        };
+        filter.set_sample_rate(sample_rate);
+        filter
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        let rc = 1.0 / (2.0 * PI * self.cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        self.alpha = match self.kind {
+            Kind::HighPass => rc / (rc + dt),
+            Kind::LowPass => dt / (rc + dt),
+        };
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let output = match self.kind {
+            Kind::HighPass => self.alpha * (self.previous_output + input - self.previous_input),
+            Kind::LowPass => self.previous_output + self.alpha * (input - self.previous_output),
+        };
+
+        self.previous_input = input;
+        self.previous_output = output;
+        output
+    }
+}
+
+/// Applies the output filters to the resampled mixer output. Each hardware stage can be toggled,
+/// clean mode replaces all of them with a DC blocker.
+#[derive(Clone)]
+pub struct FilterChain {
+    stages: [Filter; 3],
+    dc_blocker: Filter,
+    pub clean_mode: bool,
+}
+
+impl FilterChain {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            stages: [
+                Filter::new(Kind::HighPass, 90.0, sample_rate),
+                Filter::new(Kind::HighPass, 440.0, sample_rate),
+                Filter::new(Kind::LowPass, 14_000.0, sample_rate),
+            ],
+            dc_blocker: Filter::new(Kind::HighPass, CLEAN_CUTOFF, sample_rate),
+            clean_mode: false,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        for stage in self.stages.iter_mut() {
+            stage.set_sample_rate(sample_rate);
+        }
+        self.dc_blocker.set_sample_rate(sample_rate);
+    }
+
+    pub fn set_enabled(&mut self, filter: AudioFilter, enabled: bool) {
+        self.stages[filter as usize].enabled = enabled;
+    }
+
+    pub fn is_enabled(&self, filter: AudioFilter) -> bool {
+        self.stages[filter as usize].enabled
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples.iter_mut() {
+            if self.clean_mode {
+                *sample = self.dc_blocker.process(*sample);
+                continue;
+            }
+
+            for stage in self.stages.iter_mut().filter(|stage| stage.enabled) {
+                *sample = stage.process(*sample);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AudioFilter, FilterChain};
+
+    #[test]
+    fn removes_dc_offset() {
+        let mut chain = FilterChain::new(44_100);
+        let mut samples = vec![0.5f32; 44_100];
+
+        chain.process(&mut samples);
+        assert!(samples[44_099].abs() < 1e-3);
+
+        chain.set_enabled(AudioFilter::HighPass90Hz, false);
+        chain.set_enabled(AudioFilter::HighPass440Hz, false);
+        let mut samples = vec![0.5f32; 44_100];
+
+        chain.process(&mut samples);
+        assert!((samples[44_099] - 0.5).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,149 @@
+/// Units the frame counter clocks on a given cycle.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FrameClock {
+    None,
+    /// Envelopes and the triangle's linear counter
+    Quarter,
+    /// Quarter frame units plus length counters and sweep units
+    Half,
+}
+
+/// Drives the low frequency clocks of the other channels and generates the frame IRQ, timings
+/// are in CPU cycles for NTSC.
+/// https://wiki.nesdev.com/w/index.php/APU_Frame_Counter
+pub struct FrameCounter {
+    five_step_mode: bool,
+    irq_inhibit: bool,
+    cycle: u32,
+    /// Cycles left until a $4017 write takes effect and the sequence restarts.
+    reset_delay: u8,
+    pub interrupt: bool,
+}
+
+impl FrameCounter {
+    pub fn new() -> Self {
+        Self {
+            five_step_mode: false,
+            irq_inhibit: false,
+            cycle: 0,
+            reset_delay: 0,
+            interrupt: false,
+        }
+    }
+
+    /// Writes the `MI-- ----` bits of $4017. `odd_cycle` is the CPU cycle parity of the write,
+    /// which delays the sequencer reset by 3 or 4 cycles.
+    pub fn write(&mut self, value: u8, odd_cycle: bool) {
+        self.five_step_mode = (value & 0x80) != 0;
+        self.irq_inhibit = (value & 0x40) != 0;
+        if self.irq_inhibit {
+            self.interrupt = false;
+        }
+        self.reset_delay = if odd_cycle { 4 } else { 3 };
+    }
+
+    /// Clocked on every CPU cycle.
+    pub fn clock(&mut self) -> FrameClock {
+        if self.reset_delay > 0 {
+            self.reset_delay -= 1;
+            if self.reset_delay == 0 {
+                self.cycle = 0;
+                // Writing with the 5-step mode bit set immediately clocks all units
+                if self.five_step_mode {
+                    return FrameClock::Half;
+                }
+                return FrameClock::None;
+            }
+        }
+
+        self.cycle += 1;
+
+        if self.five_step_mode {
+            match self.cycle {
+                7457 => FrameClock::Quarter,
+                14913 => FrameClock::Half,
+                22371 => FrameClock::Quarter,
+                37281 => FrameClock::Half,
+                37282 => {
+                    self.cycle = 0;
+                    FrameClock::None
+                }
+                _ => FrameClock::None,
+            }
+        } else {
+            match self.cycle {
+                7457 => FrameClock::Quarter,
+                14913 => FrameClock::Half,
+                22371 => FrameClock::Quarter,
+                29828 => {
+                    self.set_interrupt();
+                    FrameClock::None
+                }
+                29829 => {
+                    self.set_interrupt();
+                    FrameClock::Half
+                }
+                29830 => {
+                    self.set_interrupt();
+                    self.cycle = 0;
+                    FrameClock::None
+                }
+                _ => FrameClock::None,
+            }
+        }
+    }
+
+    fn set_interrupt(&mut self) {
+        if !self.irq_inhibit {
+            self.interrupt = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameClock, FrameCounter};
+
+    fn run(counter: &mut FrameCounter, cycles: u32) -> Vec<FrameClock> {
+        (0..cycles)
+            .map(|_| counter.clock())
+            .filter(|clock| *clock != FrameClock::None)
+            .collect()
+    }
+
+    #[test]
+    fn four_step_mode() {
+        let mut counter = FrameCounter::new();
+        let clocks = run(&mut counter, 29830);
+
+        assert_eq!(
+            clocks,
+            [
+                FrameClock::Quarter,
+                FrameClock::Half,
+                FrameClock::Quarter,
+                FrameClock::Half
+            ]
+        );
+        assert!(counter.interrupt);
+    }
+
+    #[test]
+    fn five_step_mode() {
+        let mut counter = FrameCounter::new();
+        counter.write(0xC0, false);
+        let clocks = run(&mut counter, 3 + 37282);
+
+        assert_eq!(
+            clocks,
+            [
+                FrameClock::Half,
+                FrameClock::Quarter,
+                FrameClock::Half,
+                FrameClock::Quarter,
+                FrameClock::Half
+            ]
+        );
+        assert!(!counter.interrupt);
+    }
+}
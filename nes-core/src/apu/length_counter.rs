@@ -0,0 +1,43 @@
+/// https://wiki.nesdev.com/w/index.php/APU_Length_Counter
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// Automatically silences a channel after a given duration, unless halted.
+/// https://wiki.nesdev.com/w/index.php/APU_Length_Counter
+#[derive(Default)]
+pub struct LengthCounter {
+    counter: u8,
+    enabled: bool,
+    pub halt: bool,
+}
+
+impl LengthCounter {
+    /// Loads the counter from the 5-bit index written to the `LLLL L---` bits of the channel
+    /// length register. Has no effect while the channel is disabled through $4015.
+    pub fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(index & 0x1F) as usize];
+        }
+    }
+
+    /// Enables or disables the channel through $4015, disabling clears the counter immediately.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    /// Clocked by the frame counter on every half frame.
+    pub fn clock(&mut self) {
+        if self.counter > 0 && !self.halt {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
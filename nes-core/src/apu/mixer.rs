@@ -0,0 +1,32 @@
+/// Nonlinear DAC of the 2A03, approximated with lookup tables.
+/// https://wiki.nesdev.com/w/index.php/APU_Mixer#Lookup_Table
+pub struct Mixer {
+    pulse_table: [f32; 31],
+    tnd_table: [f32; 203],
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        let mut pulse_table = [0f32; 31];
+        let mut tnd_table = [0f32; 203];
+
+        for (n, value) in pulse_table.iter_mut().enumerate().skip(1) {
+            *value = 95.52 / (8128.0 / n as f32 + 100.0);
+        }
+        for (n, value) in tnd_table.iter_mut().enumerate().skip(1) {
+            *value = 163.67 / (24329.0 / n as f32 + 100.0);
+        }
+
+        Self {
+            pulse_table,
+            tnd_table,
+        }
+    }
+
+    /// Mixes the channel output levels into an amplitude between 0.0 and 1.0.
+    pub fn mix(&self, pulse1: u8, pulse2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+        let pulse_out = self.pulse_table[(pulse1 + pulse2) as usize];
+        let tnd_out = self.tnd_table[3 * triangle as usize + 2 * noise as usize + dmc as usize];
+        pulse_out + tnd_out
+    }
+}
@@ -0,0 +1,111 @@
+use crate::apu::pulse::Pulse;
+
+/// CPU cycles between the clocks of the envelopes and length counters (about 240Hz).
+const FRAME_PERIOD: u16 = 7457;
+
+/// Expansion audio of the MMC5: two pulse channels like the 2A03 ones, minus the sweep unit,
+/// and an 8-bit PCM channel. Only the PCM write mode is emulated.
+/// https://wiki.nesdev.com/w/index.php/MMC5_audio
+pub struct Mmc5Audio {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    pcm: u8,
+    frame_divider: u16,
+    cycles: u64,
+}
+
+impl Mmc5Audio {
+    pub fn new() -> Self {
+        Self {
+            pulse1: Pulse::without_sweep(),
+            pulse2: Pulse::without_sweep(),
+            pcm: 0,
+            frame_divider: 0,
+            cycles: 0,
+        }
+    }
+
+    /// Advances the channels by one CPU clock cycle.
+    pub fn clock(&mut self) {
+        // The envelopes and length counters run at a fixed rate instead of the frame counter
+        self.frame_divider += 1;
+        if self.frame_divider == FRAME_PERIOD {
+            self.frame_divider = 0;
+            for pulse in [&mut self.pulse1, &mut self.pulse2].iter_mut() {
+                pulse.clock_quarter_frame();
+                pulse.clock_half_frame();
+            }
+        }
+
+        if self.cycles % 2 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+
+        self.cycles += 1;
+    }
+
+    /// Amplitude of the channels, mixed like the matching 2A03 channels.
+    pub fn output(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.52 / (8128.0 / pulse + 100.0)
+        };
+
+        let pcm = (self.pcm >> 1) as f32;
+        let pcm_out = if pcm == 0.0 {
+            0.0
+        } else {
+            163.67 / (24329.0 / pcm + 100.0)
+        };
+
+        pulse_out + pcm_out
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/MMC5_audio#Registers
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            // $5001 and $5005 would be the sweep registers
+            0x5000 | 0x5002 | 0x5003 => self.pulse1.write_register(address - 0x5000, value),
+            0x5004 | 0x5006 | 0x5007 => self.pulse2.write_register(address - 0x5004, value),
+            // Writing $00 has no effect
+            0x5011 if value != 0 => self.pcm = value,
+            0x5015 => {
+                // Status ---- --21
+                self.pulse1.length_counter.set_enabled((value & 0x01) != 0);
+                self.pulse2.length_counter.set_enabled((value & 0x02) != 0);
+            }
+            _ => {}
+        }
+    }
+
+    /// Status ---- --21, the length counter state of each pulse channel ($5015).
+    pub fn read_status(&self) -> u8 {
+        (self.pulse2.length_counter.active() as u8) << 1 | self.pulse1.length_counter.active() as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mmc5Audio;
+
+    #[test]
+    fn pulse_without_sweep_muting() {
+        let mut audio = Mmc5Audio::new();
+        audio.write_register(0x5015, 0x01);
+        audio.write_register(0x5000, 0b1011_1111); // 50% duty, constant volume 15
+        audio.write_register(0x5002, 0x04);
+        audio.write_register(0x5003, 0x08); // Period 4, which mutes the 2A03 pulses
+
+        assert_eq!(audio.read_status(), 0x01);
+        let mut levels = Vec::new();
+        for _ in 0..80 {
+            audio.clock();
+            levels.push(audio.output());
+        }
+        assert!(levels.iter().any(|&level| level > 0.0));
+        assert!(levels.contains(&0.0));
+    }
+}
@@ -0,0 +1,426 @@
+mod blip_buffer;
+mod dmc;
+mod envelope;
+pub mod filter;
+mod frame_counter;
+mod length_counter;
+mod mixer;
+pub(crate) mod mmc5;
+pub(crate) mod namco163;
+mod noise;
+mod pulse;
+pub(crate) mod sunsoft5b;
+mod triangle;
+pub(crate) mod vrc6;
+pub(crate) mod vrc7;
+mod wav;
+mod waveform;
+
+use crate::apu::blip_buffer::BlipBuffer;
+use crate::apu::dmc::Dmc;
+use crate::apu::filter::AudioFilter;
+use crate::apu::filter::FilterChain;
+use crate::apu::frame_counter::FrameClock;
+use crate::apu::frame_counter::FrameCounter;
+use crate::apu::mixer::Mixer;
+use crate::apu::noise::Noise;
+use crate::apu::pulse::Pulse;
+use crate::apu::triangle::Triangle;
+pub use crate::apu::wav::WavOutput;
+pub use crate::apu::wav::WavRecorder;
+use crate::apu::waveform::WaveformTap;
+
+use std::io;
+
+/// NTSC CPU clock rate (21.477272 MHz / 12), the rate at which the APU generates its output.
+pub const CPU_CLOCK_RATE: f64 = 1_789_773.0;
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+/// Number of recent samples kept for each channel's waveform.
+const WAVEFORM_SIZE: usize = 2048;
+/// Number of samples written to a WAV recording at once.
+const RECORDING_CHUNK: usize = 1024;
+
+/// Sound sources that can be muted, soloed or inspected individually.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AudioChannel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+    /// Audio generated by the cartridge
+    Expansion,
+}
+
+impl AudioChannel {
+    pub const ALL: [AudioChannel; 6] = [
+        AudioChannel::Pulse1,
+        AudioChannel::Pulse2,
+        AudioChannel::Triangle,
+        AudioChannel::Noise,
+        AudioChannel::Dmc,
+        AudioChannel::Expansion,
+    ];
+}
+
+/// Samples recorded to a WAV file as the APU generates them, through their own resampler and
+/// filters so recording doesn't depend on the front-end reading the audio output.
+struct Recording {
+    blip: BlipBuffer,
+    filters: FilterChain,
+    output: Box<dyn WavOutput>,
+    buf: Box<[f32]>,
+}
+
+impl Recording {
+    /// Writes the samples generated so far to the output
+    fn flush(&mut self) {
+        let count = self.blip.read_samples(&mut self.buf);
+        self.filters.process(&mut self.buf[..count]);
+        self.output.push(&self.buf[..count]);
+    }
+}
+
+/// https://wiki.nesdev.com/w/index.php/APU
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_counter: FrameCounter,
+    mixer: Mixer,
+    blip: BlipBuffer,
+    filters: FilterChain,
+    muted: [bool; 6],
+    soloed: [bool; 6],
+    waveforms: Vec<WaveformTap>,
+    /// Output samples per CPU cycle, used to feed the waveforms at the output rate.
+    waveform_step: f64,
+    waveform_phase: f64,
+    sample_rate: u32,
+    recording: Option<Recording>,
+    /// Amplitude of the cartridge audio, added to the mix
+    expansion: f32,
+    /// The number of CPU clock cycles since the APU was created.
+    pub cycles: u64,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Self {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Default::default(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            frame_counter: FrameCounter::new(),
+            mixer: Mixer::new(),
+            blip: BlipBuffer::new(CPU_CLOCK_RATE, DEFAULT_SAMPLE_RATE as f64),
+            filters: FilterChain::new(DEFAULT_SAMPLE_RATE),
+            muted: [false; 6],
+            soloed: [false; 6],
+            waveforms: (0..AudioChannel::ALL.len())
+                .map(|_| WaveformTap::new(WAVEFORM_SIZE))
+                .collect(),
+            waveform_step: DEFAULT_SAMPLE_RATE as f64 / CPU_CLOCK_RATE,
+            waveform_phase: 0.0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            recording: None,
+            expansion: 0.0,
+            cycles: 0,
+        }
+    }
+
+    /// Advances the APU by one CPU clock cycle.
+    pub fn tick(&mut self) {
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        match self.frame_counter.clock() {
+            FrameClock::Quarter => self.clock_quarter_frame(),
+            FrameClock::Half => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            FrameClock::None => {}
+        }
+
+        let outputs = [
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        ];
+        let audible = |channel: AudioChannel| {
+            if self.is_channel_audible(channel) {
+                outputs[channel as usize]
+            } else {
+                0
+            }
+        };
+
+        let amplitude = self.mixer.mix(
+            audible(AudioChannel::Pulse1),
+            audible(AudioChannel::Pulse2),
+            audible(AudioChannel::Triangle),
+            audible(AudioChannel::Noise),
+            audible(AudioChannel::Dmc),
+        );
+        let expansion = if self.is_channel_audible(AudioChannel::Expansion) {
+            self.expansion
+        } else {
+            0.0
+        };
+        self.blip.set_amplitude(amplitude + expansion);
+        self.blip.clock();
+
+        if let Some(recording) = self.recording.as_mut() {
+            recording.blip.set_amplitude(amplitude + expansion);
+            recording.blip.clock();
+            if recording.blip.samples_available() >= recording.buf.len() {
+                recording.flush();
+            }
+        }
+
+        self.waveform_phase += self.waveform_step;
+        if self.waveform_phase >= 1.0 {
+            self.waveform_phase -= 1.0;
+            self.record_waveforms(&outputs);
+        }
+
+        // The pulse timers are clocked every APU cycle, which is every other CPU cycle
+        if self.cycles % 2 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+
+        self.cycles += 1;
+    }
+
+    /// Sets the amplitude of the audio generated by the cartridge, on the same scale as the
+    /// 2A03 mix. Expansion chips are mixed in through the cartridge connector.
+    pub fn set_expansion_output(&mut self, amplitude: f32) {
+        self.expansion = amplitude;
+    }
+
+    /// Sets the rate of the samples returned by `read_samples`.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.blip.set_rates(CPU_CLOCK_RATE, sample_rate as f64);
+        self.filters.set_sample_rate(sample_rate);
+        self.waveform_step = sample_rate as f64 / CPU_CLOCK_RATE;
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn set_channel_muted(&mut self, channel: AudioChannel, muted: bool) {
+        self.muted[channel as usize] = muted;
+    }
+
+    /// While any channel is soloed, only the soloed channels are heard.
+    pub fn set_channel_soloed(&mut self, channel: AudioChannel, soloed: bool) {
+        self.soloed[channel as usize] = soloed;
+    }
+
+    pub fn is_channel_audible(&self, channel: AudioChannel) -> bool {
+        if self.soloed.iter().any(|soloed| *soloed) {
+            self.soloed[channel as usize]
+        } else {
+            !self.muted[channel as usize]
+        }
+    }
+
+    /// Copies the most recent output levels of a channel into `buf`, oldest first, at the output
+    /// sample rate. Levels are normalized between 0.0 and 1.0 and not affected by muting.
+    pub fn read_waveform(&self, channel: AudioChannel, buf: &mut [f32]) -> usize {
+        self.waveforms[channel as usize].read(buf)
+    }
+
+    fn record_waveforms(&mut self, outputs: &[u8; 5]) {
+        for (i, output) in outputs.iter().enumerate() {
+            let max = if i == AudioChannel::Dmc as usize {
+                127.0
+            } else {
+                15.0
+            };
+            self.waveforms[i].push(*output as f32 / max);
+        }
+        self.waveforms[AudioChannel::Expansion as usize].push(self.expansion);
+    }
+
+    pub fn set_filter_enabled(&mut self, filter: AudioFilter, enabled: bool) {
+        self.filters.set_enabled(filter, enabled);
+        if let Some(recording) = self.recording.as_mut() {
+            recording.filters.set_enabled(filter, enabled);
+        }
+    }
+
+    pub fn is_filter_enabled(&self, filter: AudioFilter) -> bool {
+        self.filters.is_enabled(filter)
+    }
+
+    /// Clean mode bypasses the hardware filters, only removing the DC offset from the output.
+    pub fn set_clean_mode(&mut self, clean_mode: bool) {
+        self.filters.clean_mode = clean_mode;
+        if let Some(recording) = self.recording.as_mut() {
+            recording.filters.clean_mode = clean_mode;
+        }
+    }
+
+    /// Number of audio samples ready to be read.
+    pub fn samples_available(&self) -> usize {
+        self.blip.samples_available()
+    }
+
+    /// Reads the filtered audio output into `buf`, returning the number of samples written.
+    pub fn read_samples(&mut self, buf: &mut [f32]) -> usize {
+        let count = self.blip.read_samples(buf);
+        self.filters.process(&mut buf[..count]);
+        count
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Starts recording the audio output as it's generated, with the current filters. The output
+    /// should be a `WavRecorder` at the current sample rate, later changes of the sample rate
+    /// don't affect the recording. Fails if a recording is already in progress.
+    pub fn start_recording(&mut self, output: Box<dyn WavOutput>) -> io::Result<()> {
+        if self.recording.is_some() {
+            return Err(recording_in_progress());
+        }
+
+        self.recording = Some(Recording {
+            blip: BlipBuffer::new(CPU_CLOCK_RATE, self.sample_rate as f64),
+            filters: self.filters.clone(),
+            output,
+            buf: vec![0.0; RECORDING_CHUNK].into_boxed_slice(),
+        });
+        Ok(())
+    }
+
+    /// Finishes the recording in progress, writing out the samples generated so far.
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        if let Some(mut recording) = self.recording.take() {
+            recording.flush();
+            recording.output.finish()?;
+        }
+        Ok(())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Address the DMC wants to read a sample byte from, if any. The caller must perform the read
+    /// through the CPU bus, stall the CPU and hand the byte back through `dmc_dma_complete`.
+    pub fn dmc_dma_request(&self) -> Option<u16> {
+        self.dmc.dma_request()
+    }
+
+    pub fn dmc_dma_complete(&mut self, value: u8) {
+        self.dmc.dma_complete(value);
+    }
+
+    /// State of the APU's IRQ line.
+    pub fn irq_pending(&self) -> bool {
+        self.dmc.interrupt || self.frame_counter.interrupt
+    }
+
+    /// Clocks the envelopes and the triangle's linear counter.
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.clock_quarter_frame();
+        self.pulse2.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    /// Clocks the length counters and sweep units.
+    fn clock_half_frame(&mut self) {
+        self.pulse1.clock_half_frame();
+        self.pulse2.clock_half_frame();
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    /// Current output level (0 to 15) of each pulse channel.
+    pub fn pulse_output(&self) -> (u8, u8) {
+        (self.pulse1.output(), self.pulse2.output())
+    }
+
+    /// Current output level (0 to 15) of the triangle channel.
+    pub fn triangle_output(&self) -> u8 {
+        self.triangle.output()
+    }
+
+    /// Current output level (0 to 15) of the noise channel.
+    pub fn noise_output(&self) -> u8 {
+        self.noise.output()
+    }
+
+    /// Current output level (0 to 127) of the DMC.
+    pub fn dmc_output(&self) -> u8 {
+        self.dmc.output()
+    }
+
+    // #region CPU mapped registers
+    /// https://wiki.nesdev.com/w/index.php/APU_registers
+    pub fn write_register_cpu_address(&mut self, address: u16, value: u8) {
+        match address {
+            0x4000..=0x4003 => self.pulse1.write_register(address - 0x4000, value),
+            0x4004..=0x4007 => self.pulse2.write_register(address - 0x4004, value),
+            0x4008..=0x400B => self.triangle.write_register(address - 0x4008, value),
+            0x400C..=0x400F => self.noise.write_register(address - 0x400C, value),
+            0x4010..=0x4013 => self.dmc.write_register(address - 0x4010, value),
+            0x4015 => {
+                // Status ---D NT21
+                self.dmc.interrupt = false;
+                self.dmc.set_enabled((value & 0x10) != 0);
+                self.pulse1.length_counter.set_enabled((value & 0x01) != 0);
+                self.pulse2.length_counter.set_enabled((value & 0x02) != 0);
+                self.triangle
+                    .length_counter
+                    .set_enabled((value & 0x04) != 0);
+                self.noise.length_counter.set_enabled((value & 0x08) != 0);
+            }
+            0x4017 => self.frame_counter.write(value, self.cycles % 2 == 1),
+            _ => {}
+        }
+    }
+
+    pub fn read_register_cpu_address(&mut self, address: u16) -> u8 {
+        match address {
+            0x4015 => {
+                // Status IF-D NT21
+                let status = (self.dmc.interrupt as u8) << 7
+                    | (self.frame_counter.interrupt as u8) << 6
+                    | (self.dmc.active() as u8) << 4
+                    | (self.noise.length_counter.active() as u8) << 3
+                    | (self.triangle.length_counter.active() as u8) << 2
+                    | (self.pulse2.length_counter.active() as u8) << 1
+                    | (self.pulse1.length_counter.active() as u8);
+
+                // Reading the status acknowledges the frame interrupt, but not the DMC one
+                self.frame_counter.interrupt = false;
+                status
+            }
+            _ => 0, // Other APU registers are write only!
+        }
+    }
+    // #endregion
+}
+
+pub(crate) fn recording_in_progress() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "A WAV recording is already in progress",
+    )
+}
@@ -0,0 +1,155 @@
+use std::cell::Cell;
+
+/// CPU cycles taken to update one channel.
+const CHANNEL_PERIOD: u8 = 15;
+
+/// Sound registers of the first channel, the 8 channels take the last 64 bytes of sound RAM.
+const CHANNEL_REGISTERS: usize = 0x40;
+
+/// Output of a channel at full volume, close to a 2A03 pulse at full volume.
+const CHANNEL_SCALE: f32 = 0.15 / 120.0;
+
+/// Expansion audio of the Namco 163: up to 8 wavetable channels playing 4-bit samples from a
+/// 128-byte sound RAM. The chip updates a single channel every 15 CPU cycles and outputs them
+/// one at a time, which is emulated by averaging the enabled channels.
+/// https://wiki.nesdev.com/w/index.php/Namco_163_audio
+pub struct Namco163Audio {
+    pub ram: [u8; 0x80],
+    /// Address port ($F800-$FFFF) IAAA AAAA, auto-increment and address. Reads of the data port
+    /// increment it too.
+    address: Cell<u8>,
+    /// Last output of each channel, from -120 to 105
+    outputs: [i16; 8],
+    /// Channel updated next, counting down from 7
+    channel: usize,
+    divider: u8,
+}
+
+impl Namco163Audio {
+    pub fn new() -> Self {
+        Self {
+            ram: [0; 0x80],
+            address: Cell::new(0),
+            outputs: [0; 8],
+            channel: 7,
+            divider: 0,
+        }
+    }
+
+    /// Number of enabled channels (1 to 8), from bits 4-6 of $7F
+    fn enabled_channels(&self) -> usize {
+        (((self.ram[0x7F] >> 4) & 0x07) + 1) as usize
+    }
+
+    /// Advances the channels by one CPU clock cycle.
+    pub fn clock(&mut self) {
+        self.divider += 1;
+        if self.divider < CHANNEL_PERIOD {
+            return;
+        }
+        self.divider = 0;
+
+        self.update_channel(self.channel);
+
+        // Channels 7 down to 8 - N are enabled
+        self.channel = if self.channel <= 8 - self.enabled_channels() {
+            7
+        } else {
+            self.channel - 1
+        };
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/Namco_163_audio#Channel_registers
+    fn update_channel(&mut self, channel: usize) {
+        let base = CHANNEL_REGISTERS + channel * 8;
+        let registers = &mut self.ram[base..base + 8];
+
+        let frequency =
+            registers[0] as u32 | (registers[2] as u32) << 8 | ((registers[4] & 0x03) as u32) << 16;
+        let mut phase =
+            registers[1] as u32 | (registers[3] as u32) << 8 | (registers[5] as u32) << 16;
+        let length = 256 - (registers[4] & 0xFC) as u32;
+        let wave_address = registers[6] as u32;
+        let volume = (registers[7] & 0x0F) as i16;
+
+        phase = (phase + frequency) % (length << 16);
+        registers[1] = phase as u8;
+        registers[3] = (phase >> 8) as u8;
+        registers[5] = (phase >> 16) as u8;
+
+        // Samples are packed two per byte, low nibble first
+        let sample_address = ((wave_address + (phase >> 16)) & 0xFF) as usize;
+        let sample = (self.ram[sample_address / 2] >> ((sample_address & 0x01) * 4)) & 0x0F;
+
+        self.outputs[channel] = (sample as i16 - 8) * volume;
+    }
+
+    /// Amplitude of the channels.
+    pub fn output(&self) -> f32 {
+        let enabled = self.enabled_channels();
+        let sum: i16 = self.outputs[8 - enabled..].iter().sum();
+        sum as f32 / enabled as f32 * CHANNEL_SCALE
+    }
+
+    /// Address port ($F800-$FFFF) IAAA AAAA
+    pub fn write_address(&mut self, value: u8) {
+        self.address.set(value);
+    }
+
+    /// Data port ($4800-$4FFF), reading without the increment
+    pub fn peek_data(&self) -> u8 {
+        self.ram[(self.address.get() & 0x7F) as usize]
+    }
+
+    /// Data port ($4800-$4FFF)
+    pub fn read_data(&self) -> u8 {
+        let value = self.peek_data();
+        self.increment_address();
+        value
+    }
+
+    /// Data port ($4800-$4FFF)
+    pub fn write_data(&mut self, value: u8) {
+        self.ram[(self.address.get() & 0x7F) as usize] = value;
+        self.increment_address();
+    }
+
+    fn increment_address(&self) {
+        let address = self.address.get();
+        if (address & 0x80) != 0 {
+            self.address.set(0x80 | (address.wrapping_add(1) & 0x7F));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Namco163Audio;
+
+    #[test]
+    fn wavetable_playback() {
+        let mut audio = Namco163Audio::new();
+
+        // Auto-increment from $00, a 4-sample wave of 15, 15, 0, 0
+        audio.write_address(0x80);
+        audio.write_data(0xFF);
+        audio.write_data(0x00);
+        assert_eq!(audio.address.get(), 0x82);
+
+        // Channel 8: frequency $10000 (one sample per update), length 4, volume 15
+        audio.write_address(0x80 | 0x78);
+        for &value in [0x00, 0x00, 0x00, 0x00, 0xFC | 0x01, 0x00, 0x00, 0x0F].iter() {
+            audio.write_data(value);
+        }
+        assert_eq!(audio.read_data(), 0xFF);
+
+        let mut outputs = Vec::new();
+        for _ in 0..4 {
+            for _ in 0..15 {
+                audio.clock();
+            }
+            outputs.push(audio.outputs[7]);
+        }
+        assert_eq!(outputs, vec![7 * 15, -8 * 15, -8 * 15, 7 * 15]);
+    }
+}
@@ -0,0 +1,116 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+
+/// Timer periods in CPU cycles.
+/// https://wiki.nesdev.com/w/index.php/APU_Noise
+const PERIOD_TABLE_NTSC: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+/// Pseudo-random noise generator at $400C-$400F.
+/// https://wiki.nesdev.com/w/index.php/APU_Noise
+pub struct Noise {
+    /// 15-bit linear feedback shift register
+    shift_register: u16,
+    /// Short mode (93-step sequence) taps bit 6 instead of bit 1
+    short_mode: bool,
+    timer: u16,
+    timer_period: u16,
+    envelope: Envelope,
+    pub length_counter: LengthCounter,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Self {
+            shift_register: 1, // On power-up, the shift register is loaded with the value 1
+            short_mode: false,
+            timer: 0,
+            timer_period: PERIOD_TABLE_NTSC[0],
+            envelope: Default::default(),
+            length_counter: Default::default(),
+        }
+    }
+
+    /// Writes to one of the four channel registers, `index` is the address offset (0 to 3).
+    pub fn write_register(&mut self, index: u16, value: u8) {
+        match index {
+            0 => {
+                // --LC VVVV
+                self.length_counter.halt = (value & 0x20) != 0;
+                self.envelope.write_control(value);
+            }
+            1 => {} // Unused
+            2 => {
+                // M--- PPPP
+                self.short_mode = (value & 0x80) != 0;
+                self.timer_period = PERIOD_TABLE_NTSC[(value & 0x0F) as usize];
+            }
+            3 => {
+                // LLLL L---
+                self.length_counter.load(value >> 3);
+                self.envelope.restart();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocked on every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 0x01;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// Clocked by the frame counter on every quarter frame.
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Clocked by the frame counter on every half frame.
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    /// Current output level (0 to 15).
+    pub fn output(&self) -> u8 {
+        if !self.length_counter.active() || (self.shift_register & 0x01) != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Noise;
+
+    fn sequence_length(short_mode: bool) -> usize {
+        let mut noise = Noise::new();
+        noise.short_mode = short_mode;
+        let initial = noise.shift_register;
+
+        for i in 1..=0x8000 {
+            noise.timer = 0;
+            noise.clock_timer();
+            if noise.shift_register == initial {
+                return i;
+            }
+        }
+
+        unreachable!("LFSR never repeated");
+    }
+
+    #[test]
+    fn lfsr_period() {
+        assert_eq!(sequence_length(false), 32767);
+        assert_eq!(sequence_length(true), 93);
+    }
+}
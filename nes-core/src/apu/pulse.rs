@@ -0,0 +1,200 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+
+/// https://wiki.nesdev.com/w/index.php/APU_Pulse#Sequencer_behavior
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0], // 12.5%
+    [0, 1, 1, 0, 0, 0, 0, 0], // 25%
+    [0, 1, 1, 1, 1, 0, 0, 0], // 50%
+    [1, 0, 0, 1, 1, 1, 1, 1], // 25% negated
+];
+
+/// Periodically adjusts the period of the pulse channel.
+/// https://wiki.nesdev.com/w/index.php/APU_Sweep
+#[derive(Default)]
+struct Sweep {
+    enabled: bool,
+    divider_period: u8,
+    divider: u8,
+    negate: bool,
+    shift: u8,
+    reload: bool,
+}
+
+/// Square wave generator, the 2A03 has two of these at $4000-$4003 and $4004-$4007.
+/// https://wiki.nesdev.com/w/index.php/APU_Pulse
+pub struct Pulse {
+    duty: u8,
+    sequencer_step: u8,
+    timer: u16,
+    timer_period: u16,
+    envelope: Envelope,
+    sweep: Sweep,
+    pub length_counter: LengthCounter,
+    /// Pulse 1 adds the ones' complement of the change amount when negating the sweep,
+    /// pulse 2 uses the two's complement.
+    ones_complement_negate: bool,
+    /// Expansion chips copy the 2A03 pulse without its sweep unit, or its muting.
+    has_sweep: bool,
+}
+
+impl Pulse {
+    pub fn new(ones_complement_negate: bool) -> Self {
+        Self {
+            duty: 0,
+            sequencer_step: 0,
+            timer: 0,
+            timer_period: 0,
+            envelope: Default::default(),
+            sweep: Default::default(),
+            length_counter: Default::default(),
+            ones_complement_negate,
+            has_sweep: true,
+        }
+    }
+
+    /// Pulse channel of expansion chips like the MMC5, without a sweep unit.
+    pub fn without_sweep() -> Self {
+        Self {
+            has_sweep: false,
+            ..Self::new(false)
+        }
+    }
+
+    /// Writes to one of the four channel registers, `index` is the address offset (0 to 3).
+    pub fn write_register(&mut self, index: u16, value: u8) {
+        match index {
+            0 => {
+                // DDLC VVVV
+                self.duty = value >> 6;
+                self.length_counter.halt = (value & 0x20) != 0;
+                self.envelope.write_control(value);
+            }
+            1 => {
+                // EPPP NSSS
+                self.sweep.enabled = (value & 0x80) != 0;
+                self.sweep.divider_period = (value >> 4) & 0x07;
+                self.sweep.negate = (value & 0x08) != 0;
+                self.sweep.shift = value & 0x07;
+                self.sweep.reload = true;
+            }
+            2 => {
+                // TTTT TTTT
+                self.timer_period = (self.timer_period & 0x0700) | value as u16;
+            }
+            3 => {
+                // LLLL LTTT
+                self.timer_period = (self.timer_period & 0x00FF) | ((value as u16 & 0x07) << 8);
+                self.length_counter.load(value >> 3);
+                self.sequencer_step = 0;
+                self.envelope.restart();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocked on every APU cycle (every other CPU cycle).
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequencer_step = (self.sequencer_step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// Clocked by the frame counter on every quarter frame.
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Clocked by the frame counter on every half frame.
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+
+        let target = self.sweep_target_period();
+        if self.sweep.divider == 0
+            && self.sweep.enabled
+            && self.sweep.shift != 0
+            && !self.muted(target)
+        {
+            self.timer_period = target;
+        }
+
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.divider_period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/APU_Sweep#Calculating_the_target_period
+    fn sweep_target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep.shift;
+
+        if self.sweep.negate {
+            let change = if self.ones_complement_negate {
+                change + 1
+            } else {
+                change
+            };
+            self.timer_period.saturating_sub(change)
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    /// The sweep unit mutes the channel when the period is too low or the target period overflows,
+    /// even if the sweep itself is disabled.
+    fn muted(&self, target_period: u16) -> bool {
+        self.has_sweep && (self.timer_period < 8 || target_period > 0x7FF)
+    }
+
+    /// Current output level (0 to 15).
+    pub fn output(&self) -> u8 {
+        if !self.length_counter.active()
+            || self.muted(self.sweep_target_period())
+            || DUTY_TABLE[self.duty as usize][self.sequencer_step as usize] == 0
+        {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pulse;
+
+    #[test]
+    fn sweep_negate() {
+        let mut pulse1 = Pulse::new(true);
+        let mut pulse2 = Pulse::new(false);
+
+        for pulse in [&mut pulse1, &mut pulse2].iter_mut() {
+            pulse.write_register(1, 0b1000_1001); // Enabled, period 0, negate, shift 1
+            pulse.write_register(2, 0x00);
+            pulse.write_register(3, 0x01); // Period $100
+        }
+
+        assert_eq!(pulse1.sweep_target_period(), 0x100 - 0x80 - 1);
+        assert_eq!(pulse2.sweep_target_period(), 0x100 - 0x80);
+    }
+
+    #[test]
+    fn sweep_mutes_on_overflow() {
+        let mut pulse = Pulse::new(false);
+        pulse.length_counter.set_enabled(true);
+        pulse.write_register(0, 0b1011_1111); // 50% duty, constant volume 15
+        pulse.write_register(1, 0b0000_0001); // Disabled, shift 1
+        pulse.write_register(2, 0xFF);
+        pulse.write_register(3, 0b0000_1110); // Period $6FF, target $A7E
+
+        for _ in 0..32 {
+            pulse.clock_timer();
+            assert_eq!(pulse.output(), 0);
+        }
+    }
+}
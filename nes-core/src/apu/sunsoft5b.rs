@@ -0,0 +1,259 @@
+/// CPU cycles per step of the tone, noise and envelope generators.
+const CLOCK_DIVIDER: u8 = 16;
+
+/// Output of one channel at the loudest level, close to a 2A03 pulse at full volume.
+const CHANNEL_SCALE: f32 = 0.15;
+
+/// 17-bit noise generator of the YM2149, shifted once every two noise periods.
+struct Noise {
+    period: u8,
+    timer: u16,
+    shift_register: u32,
+}
+
+impl Noise {
+    fn clock(&mut self) {
+        self.timer += 1;
+        if self.timer >= (self.period.max(1) as u16) * 2 {
+            self.timer = 0;
+            let feedback = (self.shift_register ^ (self.shift_register >> 3)) & 0x01;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 16);
+        }
+    }
+
+    fn output(&self) -> bool {
+        (self.shift_register & 0x01) != 0
+    }
+}
+
+/// 32-step envelope shared by the three channels.
+struct Envelope {
+    period: u16,
+    timer: u16,
+    /// Shape ---- CAaH: continue, attack, alternate, hold
+    shape: u8,
+    step: u8,
+    attack: bool,
+    holding: bool,
+}
+
+impl Envelope {
+    fn write_shape(&mut self, value: u8) {
+        self.shape = value & 0x0F;
+        self.timer = 0;
+        self.step = 0;
+        self.attack = (value & 0x04) != 0;
+        self.holding = false;
+    }
+
+    fn clock(&mut self) {
+        self.timer += 1;
+        if self.timer < self.period.max(1) {
+            return;
+        }
+        self.timer = 0;
+
+        if self.holding {
+            return;
+        }
+
+        if self.step < 31 {
+            self.step += 1;
+            return;
+        }
+
+        let continues = (self.shape & 0x08) != 0;
+        let alternate = (self.shape & 0x02) != 0;
+        let hold = (self.shape & 0x01) != 0;
+
+        if !continues {
+            // Every shape without continue ends silent
+            self.attack = false;
+            self.holding = true;
+        } else if hold {
+            self.attack ^= alternate;
+            self.holding = true;
+        } else {
+            self.attack ^= alternate;
+            self.step = 0;
+        }
+    }
+
+    /// Current level (0 to 31).
+    fn output(&self) -> u8 {
+        if self.attack {
+            self.step
+        } else {
+            31 - self.step
+        }
+    }
+}
+
+/// Expansion audio of the Sunsoft 5B, a licensed YM2149F: three square channels that can be
+/// mixed with a shared noise generator, each with a fixed volume or a shared envelope.
+/// https://wiki.nesdev.com/w/index.php/Sunsoft_5B_audio
+pub struct Sunsoft5bAudio {
+    /// Register selected through $C000
+    address: u8,
+    tone_periods: [u16; 3],
+    tone_timers: [u16; 3],
+    tone_outputs: [bool; 3],
+    /// Mixer (R7) --CB Acba, noise (upper) and tone (lower) disable bits
+    mixer: u8,
+    /// Channel volume (R8-RA) ---E VVVV
+    volumes: [u8; 3],
+    noise: Noise,
+    envelope: Envelope,
+    divider: u8,
+    /// Amplitude of each of the 32 logarithmic levels, 1.5dB apart
+    levels: [f32; 32],
+}
+
+impl Sunsoft5bAudio {
+    pub fn new() -> Self {
+        let mut levels = [0f32; 32];
+        for (i, level) in levels.iter_mut().enumerate().skip(1) {
+            *level = 10f32.powf((i as f32 - 31.0) * 1.5 / 20.0);
+        }
+
+        Self {
+            address: 0,
+            tone_periods: [0; 3],
+            tone_timers: [0; 3],
+            tone_outputs: [false; 3],
+            mixer: 0,
+            volumes: [0; 3],
+            noise: Noise {
+                period: 0,
+                timer: 0,
+                shift_register: 1,
+            },
+            envelope: Envelope {
+                period: 0,
+                timer: 0,
+                shape: 0,
+                step: 0,
+                attack: false,
+                holding: true,
+            },
+            divider: 0,
+            levels,
+        }
+    }
+
+    /// Advances the channels by one CPU clock cycle.
+    pub fn clock(&mut self) {
+        self.divider += 1;
+        if self.divider < CLOCK_DIVIDER {
+            return;
+        }
+        self.divider = 0;
+
+        for channel in 0..3 {
+            self.tone_timers[channel] += 1;
+            if self.tone_timers[channel] >= self.tone_periods[channel].max(1) {
+                self.tone_timers[channel] = 0;
+                self.tone_outputs[channel] = !self.tone_outputs[channel];
+            }
+        }
+
+        self.noise.clock();
+        self.envelope.clock();
+    }
+
+    /// Amplitude of the channels, the 5B mixes them linearly.
+    pub fn output(&self) -> f32 {
+        (0..3)
+            .map(|channel| self.levels[self.channel_level(channel) as usize])
+            .sum::<f32>()
+            * CHANNEL_SCALE
+    }
+
+    /// Level (0 to 31) of a channel, 4-bit volumes use every other envelope level.
+    fn channel_level(&self, channel: usize) -> u8 {
+        let tone_disabled = (self.mixer >> channel) & 0x01 != 0;
+        let noise_disabled = (self.mixer >> (channel + 3)) & 0x01 != 0;
+
+        let tone = self.tone_outputs[channel] || tone_disabled;
+        let noise = self.noise.output() || noise_disabled;
+        if !tone || !noise {
+            return 0;
+        }
+
+        let volume = self.volumes[channel];
+        if (volume & 0x10) != 0 {
+            self.envelope.output()
+        } else if (volume & 0x0F) == 0 {
+            0
+        } else {
+            (volume & 0x0F) * 2 + 1
+        }
+    }
+
+    /// Audio register select ($C000-$DFFF) ---- RRRR
+    pub fn write_address(&mut self, value: u8) {
+        self.address = value & 0x0F;
+    }
+
+    /// Audio register write ($E000-$FFFF)
+    /// https://wiki.nesdev.com/w/index.php/Sunsoft_5B_audio#Sound_registers
+    pub fn write_data(&mut self, value: u8) {
+        let channel = (self.address / 2) as usize;
+
+        match self.address {
+            0x00 | 0x02 | 0x04 => {
+                self.tone_periods[channel] = (self.tone_periods[channel] & 0x0F00) | value as u16
+            }
+            0x01 | 0x03 | 0x05 => {
+                self.tone_periods[channel] =
+                    (self.tone_periods[channel] & 0x00FF) | ((value as u16 & 0x0F) << 8)
+            }
+            0x06 => self.noise.period = value & 0x1F,
+            0x07 => self.mixer = value,
+            0x08..=0x0A => self.volumes[(self.address - 0x08) as usize] = value & 0x1F,
+            0x0B => self.envelope.period = (self.envelope.period & 0xFF00) | value as u16,
+            0x0C => self.envelope.period = (self.envelope.period & 0x00FF) | (value as u16) << 8,
+            0x0D => self.envelope.write_shape(value),
+            // I/O ports, not connected
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sunsoft5bAudio;
+
+    fn write(audio: &mut Sunsoft5bAudio, register: u8, value: u8) {
+        audio.write_address(register);
+        audio.write_data(value);
+    }
+
+    #[test]
+    fn tone_and_envelope() {
+        let mut audio = Sunsoft5bAudio::new();
+        write(&mut audio, 0x07, 0b0011_1110); // Only the tone of channel A
+        write(&mut audio, 0x00, 2);
+        write(&mut audio, 0x08, 0x0F);
+
+        // Half period of 2 * 16 CPU cycles
+        let mut levels = Vec::new();
+        for _ in 0..32 {
+            audio.clock();
+            levels.push(audio.channel_level(0));
+        }
+        assert_eq!(levels[15], 0);
+        assert_eq!(levels[31], 31);
+
+        // Attack then hold, the level climbs once and stays at the top
+        write(&mut audio, 0x07, 0b0011_1111);
+        write(&mut audio, 0x08, 0x10);
+        write(&mut audio, 0x0B, 1);
+        write(&mut audio, 0x0D, 0b1101);
+        assert_eq!(audio.channel_level(0), 0);
+        for _ in 0..16 * 40 {
+            audio.clock();
+        }
+        assert_eq!(audio.channel_level(0), 31);
+    }
+}
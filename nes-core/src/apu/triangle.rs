@@ -0,0 +1,85 @@
+use crate::apu::length_counter::LengthCounter;
+
+/// https://wiki.nesdev.com/w/index.php/APU_Triangle
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+/// Quantized triangle wave generator at $4008-$400B.
+/// https://wiki.nesdev.com/w/index.php/APU_Triangle
+#[derive(Default)]
+pub struct Triangle {
+    sequencer_step: u8,
+    timer: u16,
+    timer_period: u16,
+    /// Also used as the length counter halt flag
+    control: bool,
+    linear_counter: u8,
+    linear_counter_period: u8,
+    linear_counter_reload: bool,
+    pub length_counter: LengthCounter,
+}
+
+impl Triangle {
+    /// Writes to one of the four channel registers, `index` is the address offset (0 to 3).
+    pub fn write_register(&mut self, index: u16, value: u8) {
+        match index {
+            0 => {
+                // CRRR RRRR
+                self.control = (value & 0x80) != 0;
+                self.length_counter.halt = self.control;
+                self.linear_counter_period = value & 0x7F;
+            }
+            1 => {} // Unused
+            2 => {
+                // TTTT TTTT
+                self.timer_period = (self.timer_period & 0x0700) | value as u16;
+            }
+            3 => {
+                // LLLL LTTT
+                self.timer_period = (self.timer_period & 0x00FF) | ((value as u16 & 0x07) << 8);
+                self.length_counter.load(value >> 3);
+                self.linear_counter_reload = true;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocked on every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            // The sequencer is silenced by halting it, so the output stays at the current step
+            if self.linear_counter > 0 && self.length_counter.active() {
+                self.sequencer_step = (self.sequencer_step + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// Clocked by the frame counter on every quarter frame.
+    /// https://wiki.nesdev.com/w/index.php/APU_Triangle#Linear_counter
+    pub fn clock_quarter_frame(&mut self) {
+        if self.linear_counter_reload {
+            self.linear_counter = self.linear_counter_period;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+
+        if !self.control {
+            self.linear_counter_reload = false;
+        }
+    }
+
+    /// Clocked by the frame counter on every half frame.
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    /// Current output level (0 to 15).
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.sequencer_step as usize]
+    }
+}
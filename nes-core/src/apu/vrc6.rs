@@ -0,0 +1,183 @@
+/// Square wave generator of the VRC6, with 8 duty cycles and a digitized mode.
+/// https://wiki.nesdev.com/w/index.php/VRC6_audio#Pulse_Channels
+#[derive(Default)]
+struct Vrc6Pulse {
+    /// MDDD VVVV
+    control: u8,
+    period: u16,
+    enabled: bool,
+    divider: u16,
+    step: u8,
+}
+
+impl Vrc6Pulse {
+    fn write_register(&mut self, index: u16, value: u8) {
+        match index {
+            0 => self.control = value,
+            1 => self.period = (self.period & 0x0F00) | value as u16,
+            2 => {
+                // E--- PPPP
+                self.period = (self.period & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.enabled = (value & 0x80) != 0;
+                if !self.enabled {
+                    self.step = 0;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if self.divider == 0 {
+            self.divider = self.period >> shift;
+            self.step = (self.step + 1) & 0x0F;
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    /// Current output level (0 to 15).
+    fn output(&self) -> u8 {
+        let volume = self.control & 0x0F;
+        let duty = (self.control >> 4) & 0x07;
+        let digitized = (self.control & 0x80) != 0;
+
+        if self.enabled && (digitized || self.step <= duty) {
+            volume
+        } else {
+            0
+        }
+    }
+}
+
+/// Sawtooth generator of the VRC6, adding its rate to an accumulator every other step.
+/// https://wiki.nesdev.com/w/index.php/VRC6_audio#Saw_Channel
+#[derive(Default)]
+struct Vrc6Saw {
+    /// --AA AAAA
+    rate: u8,
+    period: u16,
+    enabled: bool,
+    divider: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Vrc6Saw {
+    fn write_register(&mut self, index: u16, value: u8) {
+        match index {
+            0 => self.rate = value & 0x3F,
+            1 => self.period = (self.period & 0x0F00) | value as u16,
+            2 => {
+                self.period = (self.period & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.enabled = (value & 0x80) != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.divider == 0 {
+            self.divider = self.period >> shift;
+            self.step += 1;
+            if self.step == 14 {
+                self.step = 0;
+                self.accumulator = 0;
+            } else if (self.step & 0x01) == 0 {
+                self.accumulator = self.accumulator.wrapping_add(self.rate);
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    /// Current output level (0 to 31), the high 5 bits of the accumulator.
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+/// Expansion audio of the Konami VRC6: two pulse channels and a sawtooth.
+/// https://wiki.nesdev.com/w/index.php/VRC6_audio
+pub struct Vrc6Audio {
+    pulse1: Vrc6Pulse,
+    pulse2: Vrc6Pulse,
+    saw: Vrc6Saw,
+    /// Frequency control ($9003) ---- -ABH
+    frequency_control: u8,
+}
+
+impl Vrc6Audio {
+    pub fn new() -> Self {
+        Self {
+            pulse1: Default::default(),
+            pulse2: Default::default(),
+            saw: Default::default(),
+            frequency_control: 0,
+        }
+    }
+
+    /// Advances the channels by one CPU clock cycle.
+    pub fn clock(&mut self) {
+        // Halt stops all channels, the other bits speed them up 16 or 256 times
+        let shift = match self.frequency_control {
+            control if (control & 0x01) != 0 => return,
+            control if (control & 0x04) != 0 => 8,
+            control if (control & 0x02) != 0 => 4,
+            _ => 0,
+        };
+
+        self.pulse1.clock(shift);
+        self.pulse2.clock(shift);
+        self.saw.clock(shift);
+    }
+
+    /// Amplitude of the channels. The VRC6 mixes linearly, with steps about as loud as the
+    /// 2A03 pulse steps.
+    pub fn output(&self) -> f32 {
+        let level = self.pulse1.output() + self.pulse2.output() + self.saw.output();
+        level as f32 * 0.00752
+    }
+
+    /// Writes one of the audio registers, `address` is the VRC6a address ($9000-$B002).
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x9000..=0x9002 => self.pulse1.write_register(address - 0x9000, value),
+            0x9003 => self.frequency_control = value,
+            0xA000..=0xA002 => self.pulse2.write_register(address - 0xA000, value),
+            0xB000..=0xB002 => self.saw.write_register(address - 0xB000, value),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Vrc6Audio;
+
+    #[test]
+    fn saw_ramp() {
+        let mut audio = Vrc6Audio::new();
+        audio.write_register(0xB000, 0x0A);
+        audio.write_register(0xB001, 0x00);
+        audio.write_register(0xB002, 0x80); // Period 0, clocked every cycle
+
+        let mut levels = Vec::new();
+        for _ in 0..14 {
+            audio.clock();
+            levels.push(audio.saw.output());
+        }
+
+        // Six additions of 10, then back to 0 on the 14th step
+        assert_eq!(levels[11], (6 * 10) >> 3);
+        assert_eq!(levels[13], 0);
+    }
+}
@@ -0,0 +1,450 @@
+/// CPU cycles per sample of the FM synthesizer, which runs at 3.58MHz / 72 (about 49.7kHz).
+const SAMPLE_PERIOD: u8 = 36;
+
+/// Output of a channel at full volume, a bit under a 2A03 pulse at full volume as the FM waves
+/// use their whole range.
+const CHANNEL_SCALE: f32 = 0.1;
+
+const SINE_TABLE_SIZE: usize = 1024;
+
+/// Bits of the phase counters, a full wave cycle is 2^18.
+const PHASE_BITS: u32 = 18;
+
+/// Loudest envelope attenuation, in steps of 0.375dB (48dB).
+const ENVELOPE_MAX: u32 = 127;
+
+/// Samples per cycle of the tremolo (3.7Hz) and vibrato (6.4Hz) oscillators.
+const TREMOLO_PERIOD: u32 = 13432;
+const VIBRATO_PERIOD: u32 = 7768;
+
+/// Tremolo depth in dB.
+const TREMOLO_DEPTH: f32 = 4.8;
+
+/// Frequency multipliers, doubled to keep the 1/2 of setting 0 an integer.
+const MULTIPLIERS: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+/// Key scale attenuation in dB at octave 7, by the upper 4 bits of the frequency number.
+const KEY_SCALE_LEVELS: [f32; 16] = [
+    0.0, 9.0, 12.0, 13.875, 15.0, 16.125, 16.875, 17.625, 18.0, 18.75, 19.125, 19.5, 19.875, 20.25,
+    20.625, 21.0,
+];
+
+/// Key scale attenuation slopes (0, 1.5, 3 and 6dB per octave), relative to the table above.
+const KEY_SCALE_FACTORS: [f32; 4] = [0.0, 0.25, 0.5, 1.0];
+
+const VIBRATO_STEPS: [i32; 8] = [0, 1, 2, 1, 0, -1, -2, -1];
+
+/// Built-in instruments of the VRC7, patch 0 is the custom instrument.
+/// https://wiki.nesdev.com/w/index.php/VRC7_audio#Internal_patch_set
+const PATCHES: [[u8; 8]; 16] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27], // Buzzy bell
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12], // Guitar
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12], // Wurly
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27], // Flute
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28], // Clarinet
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4], // Synth
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07], // Trumpet
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17], // Organ
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01], // Bells
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02], // Vibes
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12], // Vibraphone
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16], // Tutti
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02], // Fretless
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6], // Synth bass
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06], // Sweep
+];
+
+#[derive(Copy, Clone, PartialEq)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// Operator settings decoded from an instrument. `index` is 0 for the modulator and 1 for the
+/// carrier.
+/// https://wiki.nesdev.com/w/index.php/VRC7_audio#Custom_Instrument
+struct OperatorPatch {
+    tremolo: bool,
+    vibrato: bool,
+    /// Holds the sustain level until key off, instead of decaying with the release rate
+    sustained: bool,
+    key_scale_rate: bool,
+    multiplier: u32,
+    key_scale_level: usize,
+    rectified: bool,
+    attack_rate: u8,
+    decay_rate: u8,
+    sustain_level: u32,
+    release_rate: u8,
+}
+
+impl OperatorPatch {
+    fn new(patch: &[u8; 8], index: usize) -> Self {
+        let flags = patch[index];
+        Self {
+            tremolo: (flags & 0x80) != 0,
+            vibrato: (flags & 0x40) != 0,
+            sustained: (flags & 0x20) != 0,
+            key_scale_rate: (flags & 0x10) != 0,
+            multiplier: MULTIPLIERS[(flags & 0x0F) as usize],
+            key_scale_level: (patch[2 + index] >> 6) as usize,
+            rectified: (patch[3] & (0x08 << index)) != 0,
+            attack_rate: patch[4 + index] >> 4,
+            decay_rate: patch[4 + index] & 0x0F,
+            sustain_level: (patch[6 + index] >> 4) as u32 * 8,
+            release_rate: patch[6 + index] & 0x0F,
+        }
+    }
+}
+
+struct Operator {
+    phase: u32,
+    state: EnvelopeState,
+    /// Envelope attenuation (0 to 127) in steps of 0.375dB
+    level: u32,
+    counter: u32,
+    /// Last two outputs, for the modulator feedback
+    outputs: [f32; 2],
+}
+
+impl Operator {
+    fn new() -> Self {
+        Self {
+            phase: 0,
+            state: EnvelopeState::Release,
+            level: ENVELOPE_MAX,
+            counter: 0,
+            outputs: [0.0; 2],
+        }
+    }
+
+    fn key_on(&mut self) {
+        self.phase = 0;
+        self.counter = 0;
+        self.state = EnvelopeState::Attack;
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/VRC7_audio#Envelope
+    fn clock_envelope(&mut self, patch: &OperatorPatch, channel: &Channel) {
+        let rate = match self.state {
+            EnvelopeState::Attack => patch.attack_rate,
+            EnvelopeState::Decay => patch.decay_rate,
+            EnvelopeState::Sustain if patch.sustained => 0,
+            EnvelopeState::Sustain => patch.release_rate,
+            EnvelopeState::Release if channel.sustain => 5,
+            EnvelopeState::Release if patch.sustained => patch.release_rate,
+            EnvelopeState::Release => 7,
+        };
+        if rate == 0 {
+            return;
+        }
+
+        let key_scale = (channel.block << 1 | channel.frequency >> 8) as u8;
+        let key_scale = if patch.key_scale_rate {
+            key_scale
+        } else {
+            key_scale >> 2
+        };
+        let rate = (rate * 4 + key_scale).min(63) as u32;
+
+        // Steps happen at (4 to 7) * 2^(rate / 4) / 65536 per sample
+        self.counter += (4 + (rate & 0x03)) << (rate >> 2);
+        let steps = self.counter >> 16;
+        self.counter &= 0xFFFF;
+
+        match self.state {
+            EnvelopeState::Attack if rate >= 60 => self.level = 0,
+            EnvelopeState::Attack => {
+                // Exponential rise, each step cutting an eighth of the attenuation
+                for _ in 0..steps {
+                    self.level = self.level.saturating_sub((self.level >> 3) + 1);
+                }
+            }
+            _ => self.level = (self.level + steps).min(ENVELOPE_MAX),
+        }
+
+        match self.state {
+            EnvelopeState::Attack if self.level == 0 => self.state = EnvelopeState::Decay,
+            EnvelopeState::Decay if self.level >= patch.sustain_level => {
+                self.state = EnvelopeState::Sustain
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Default)]
+struct Channel {
+    /// Frequency number ($10-$15 and bit 0 of $20-$25)
+    frequency: u16,
+    /// Octave ($20-$25) --SK OOOF
+    block: u16,
+    sustain: bool,
+    key_on: bool,
+    /// Instrument and volume ($30-$35) IIII VVVV
+    instrument: u8,
+    volume: u8,
+}
+
+/// Expansion audio of the Konami VRC7, a cut down Yamaha YM2413 (OPLL) with six two-operator
+/// FM channels, 15 built-in instruments and a custom one. Rhythm mode isn't available.
+/// https://wiki.nesdev.com/w/index.php/VRC7_audio
+pub struct Vrc7Audio {
+    /// Register selected through $9010
+    address: u8,
+    custom_patch: [u8; 8],
+    channels: [Channel; 6],
+    /// Modulator and carrier of each channel
+    operators: [[Operator; 2]; 6],
+    sine: Vec<f32>,
+    tremolo_counter: u32,
+    vibrato_counter: u32,
+    divider: u8,
+    sample: f32,
+}
+
+impl Vrc7Audio {
+    pub fn new() -> Self {
+        let sine = (0..SINE_TABLE_SIZE)
+            .map(|i| (i as f32 * 2.0 * std::f32::consts::PI / SINE_TABLE_SIZE as f32).sin())
+            .collect();
+
+        Self {
+            address: 0,
+            custom_patch: [0; 8],
+            channels: Default::default(),
+            operators: [
+                [Operator::new(), Operator::new()],
+                [Operator::new(), Operator::new()],
+                [Operator::new(), Operator::new()],
+                [Operator::new(), Operator::new()],
+                [Operator::new(), Operator::new()],
+                [Operator::new(), Operator::new()],
+            ],
+            sine,
+            tremolo_counter: 0,
+            vibrato_counter: 0,
+            divider: 0,
+            sample: 0.0,
+        }
+    }
+
+    /// Advances the synthesizer by one CPU clock cycle.
+    pub fn clock(&mut self) {
+        self.divider += 1;
+        if self.divider < SAMPLE_PERIOD {
+            return;
+        }
+        self.divider = 0;
+
+        self.tremolo_counter = (self.tremolo_counter + 1) % TREMOLO_PERIOD;
+        self.vibrato_counter = (self.vibrato_counter + 1) % VIBRATO_PERIOD;
+
+        self.sample = (0..6).map(|channel| self.channel_sample(channel)).sum();
+    }
+
+    /// Amplitude of the channels.
+    pub fn output(&self) -> f32 {
+        self.sample * CHANNEL_SCALE
+    }
+
+    fn patch(&self, instrument: u8) -> &[u8; 8] {
+        if instrument == 0 {
+            &self.custom_patch
+        } else {
+            &PATCHES[instrument as usize]
+        }
+    }
+
+    /// Tremolo attenuation in dB, a triangle wave
+    fn tremolo(&self) -> f32 {
+        let position = self.tremolo_counter as f32 / TREMOLO_PERIOD as f32;
+        TREMOLO_DEPTH * (1.0 - (position * 2.0 - 1.0).abs())
+    }
+
+    fn channel_sample(&mut self, index: usize) -> f32 {
+        let channel = &self.channels[index];
+        let patch = *self.patch(channel.instrument);
+        let tremolo = self.tremolo();
+        let vibrato = VIBRATO_STEPS[(self.vibrato_counter * 8 / VIBRATO_PERIOD) as usize];
+
+        let modulator_patch = OperatorPatch::new(&patch, 0);
+        let carrier_patch = OperatorPatch::new(&patch, 1);
+
+        // Key scaling attenuates high notes, from the upper bits of the frequency and octave
+        let key_scale = (KEY_SCALE_LEVELS[(channel.frequency >> 5) as usize]
+            - 6.0 * (7 - channel.block) as f32)
+            .max(0.0);
+
+        let modulator_attenuation = (patch[2] & 0x3F) as f32 * 0.75
+            + key_scale * KEY_SCALE_FACTORS[modulator_patch.key_scale_level];
+        let carrier_attenuation = channel.volume as f32 * 3.0
+            + key_scale * KEY_SCALE_FACTORS[carrier_patch.key_scale_level];
+
+        // Feedback of the modulator on itself, up to 2 wave cycles
+        let feedback_level = patch[3] & 0x07;
+        let [modulator, carrier] = &mut self.operators[index];
+        let feedback = if feedback_level == 0 {
+            0.0
+        } else {
+            (modulator.outputs[0] + modulator.outputs[1]) * 2f32.powi(feedback_level as i32 - 7)
+        };
+
+        let modulation = Self::operator_sample(
+            &self.sine,
+            modulator,
+            &modulator_patch,
+            channel,
+            modulator_attenuation,
+            tremolo,
+            vibrato,
+            feedback,
+        );
+        modulator.outputs = [modulator.outputs[1], modulation];
+
+        // The modulator shifts the carrier phase by up to 4 wave cycles
+        Self::operator_sample(
+            &self.sine,
+            carrier,
+            &carrier_patch,
+            channel,
+            carrier_attenuation,
+            tremolo,
+            vibrato,
+            modulation * 4.0,
+        )
+    }
+
+    /// Clocks the phase and envelope of an operator and returns its output (-1.0 to 1.0).
+    /// `modulation` shifts the phase, in wave cycles.
+    #[allow(clippy::too_many_arguments)]
+    fn operator_sample(
+        sine: &[f32],
+        operator: &mut Operator,
+        patch: &OperatorPatch,
+        channel: &Channel,
+        attenuation: f32,
+        tremolo: f32,
+        vibrato: i32,
+        modulation: f32,
+    ) -> f32 {
+        let mut frequency = channel.frequency as i32;
+        if patch.vibrato {
+            frequency += (frequency >> 7) * vibrato;
+        }
+        let increment = (((frequency as u32) << channel.block) * patch.multiplier) >> 2;
+        operator.phase = (operator.phase + increment) & ((1 << PHASE_BITS) - 1);
+
+        operator.clock_envelope(patch, channel);
+        if operator.level >= ENVELOPE_MAX {
+            return 0.0;
+        }
+
+        let mut attenuation = attenuation + operator.level as f32 * 0.375;
+        if patch.tremolo {
+            attenuation += tremolo;
+        }
+
+        let position = operator.phase as f32 / (1 << PHASE_BITS) as f32 + modulation;
+        let index = ((position * SINE_TABLE_SIZE as f32) as i32).rem_euclid(SINE_TABLE_SIZE as i32);
+        let wave = sine[index as usize];
+        let wave = if patch.rectified && wave < 0.0 {
+            0.0
+        } else {
+            wave
+        };
+
+        // 10^(-dB / 20)
+        wave * (-attenuation * 0.115_129_25).exp()
+    }
+
+    /// Audio register select ($9010)
+    pub fn write_address(&mut self, value: u8) {
+        self.address = value;
+    }
+
+    /// Audio register write ($9030)
+    /// https://wiki.nesdev.com/w/index.php/VRC7_audio#Channel_registers
+    pub fn write_data(&mut self, value: u8) {
+        let index = (self.address & 0x0F) as usize;
+
+        match self.address {
+            0x00..=0x07 => self.custom_patch[index] = value,
+            0x10..=0x15 => {
+                let channel = &mut self.channels[index];
+                channel.frequency = (channel.frequency & 0x100) | value as u16;
+            }
+            0x20..=0x25 => {
+                let channel = &mut self.channels[index];
+                channel.frequency = (channel.frequency & 0xFF) | ((value as u16 & 0x01) << 8);
+                channel.block = (value as u16 >> 1) & 0x07;
+                channel.sustain = (value & 0x20) != 0;
+
+                let key_on = (value & 0x10) != 0;
+                if key_on && !channel.key_on {
+                    for operator in self.operators[index].iter_mut() {
+                        operator.key_on();
+                    }
+                } else if !key_on && channel.key_on {
+                    for operator in self.operators[index].iter_mut() {
+                        operator.state = EnvelopeState::Release;
+                    }
+                }
+                channel.key_on = key_on;
+            }
+            0x30..=0x35 => {
+                let channel = &mut self.channels[index];
+                channel.instrument = value >> 4;
+                channel.volume = value & 0x0F;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Vrc7Audio;
+
+    fn write(audio: &mut Vrc7Audio, register: u8, value: u8) {
+        audio.write_address(register);
+        audio.write_data(value);
+    }
+
+    #[test]
+    fn custom_instrument_pitch() {
+        let mut audio = Vrc7Audio::new();
+
+        // Sustained sine carrier with instant attack and a silent modulator
+        for (register, &value) in [0x21, 0x21, 0x3F, 0x00, 0xF0, 0xF0, 0x00, 0x00]
+            .iter()
+            .enumerate()
+        {
+            write(&mut audio, register as u8, value);
+        }
+
+        // Frequency number 288 ($120) at octave 4, about 437Hz
+        write(&mut audio, 0x30, 0x00);
+        write(&mut audio, 0x10, 0x20);
+        write(&mut audio, 0x20, 0x10 | 4 << 1 | 0x01);
+
+        // A tenth of a second
+        let mut crossings = 0;
+        let mut previous = 0.0;
+        for _ in 0..4972 {
+            for _ in 0..36 {
+                audio.clock();
+            }
+            let sample = audio.output();
+            if previous <= 0.0 && sample > 0.0 {
+                crossings += 1;
+            }
+            previous = sample;
+        }
+
+        assert!((42..=45).contains(&crossings), "{} crossings", crossings);
+    }
+}
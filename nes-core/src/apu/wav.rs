@@ -0,0 +1,193 @@
+use std::io;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+
+const CHANNELS: u16 = 1;
+const BITS_PER_SAMPLE: u16 = 16;
+const BLOCK_ALIGN: u16 = CHANNELS * BITS_PER_SAMPLE / 8;
+/// Offsets of the RIFF chunk size and data subchunk size in the header
+const RIFF_SIZE_OFFSET: u64 = 4;
+const DATA_SIZE_OFFSET: u64 = 40;
+/// Size written in the header of streams, whose length isn't known until they end
+const UNKNOWN_SIZE: u32 = 0xFFFF_FFFF;
+
+/// Records mono 16-bit PCM audio into a WAV stream. Samples are written as they come, with the
+/// header sizes left unknown like most streaming encoders do. Seekable sinks get the sizes filled
+/// in when the recording is finished or dropped.
+/// http://soundfile.sapp.org/doc/WaveFormat/
+pub struct WavRecorder<W: Write> {
+    sink: Option<W>,
+    data_size: u32,
+    /// First write error, reported by `finish` since samples are pushed from the audio path
+    error: Option<io::Error>,
+    /// Fills in the header sizes, only set for seekable sinks
+    write_sizes: Option<fn(&mut W, u32) -> io::Result<()>>,
+}
+
+impl<W: Write> WavRecorder<W> {
+    /// Writes the header to any sink, pipes and sockets included.
+    pub fn new(sink: W, sample_rate: u32) -> io::Result<Self> {
+        Self::start(sink, sample_rate, None)
+    }
+
+    fn start(
+        mut sink: W,
+        sample_rate: u32,
+        write_sizes: Option<fn(&mut W, u32) -> io::Result<()>>,
+    ) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&UNKNOWN_SIZE.to_le_bytes());
+        header.extend_from_slice(b"WAVE");
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&16u32.to_le_bytes()); // Subchunk size
+        header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        header.extend_from_slice(&CHANNELS.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * BLOCK_ALIGN as u32).to_le_bytes());
+        header.extend_from_slice(&BLOCK_ALIGN.to_le_bytes());
+        header.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&UNKNOWN_SIZE.to_le_bytes());
+        sink.write_all(&header)?;
+
+        Ok(Self {
+            sink: Some(sink),
+            data_size: 0,
+            error: None,
+            write_sizes,
+        })
+    }
+
+    /// Appends samples between -1.0 and 1.0.
+    pub fn push(&mut self, samples: &[f32]) {
+        if self.error.is_some() {
+            return;
+        }
+        let sink = match self.sink.as_mut() {
+            Some(sink) => sink,
+            None => return,
+        };
+
+        let data: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| {
+                let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                sample.to_le_bytes().to_vec()
+            })
+            .collect();
+
+        match sink.write_all(&data) {
+            Ok(()) => self.data_size = self.data_size.saturating_add(data.len() as u32),
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Flushes the sink and fills in the header sizes if it's seekable, giving it back.
+    pub fn finish(mut self) -> io::Result<W> {
+        let result = self.end();
+        let sink = self.sink.take().unwrap();
+        result.map(|_| sink)
+    }
+
+    fn end(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        let sink = match self.sink.as_mut() {
+            Some(sink) => sink,
+            None => return Ok(()),
+        };
+
+        if let Some(write_sizes) = self.write_sizes {
+            write_sizes(sink, self.data_size)?;
+        }
+        sink.flush()
+    }
+}
+
+impl<W: Write + Seek> WavRecorder<W> {
+    /// Writes the header to a seekable sink, which gets the sizes filled in at the end.
+    pub fn seekable(sink: W, sample_rate: u32) -> io::Result<Self> {
+        Self::start(sink, sample_rate, Some(write_sizes::<W>))
+    }
+}
+
+impl<W: Write> Drop for WavRecorder<W> {
+    /// Keeps the file playable when the recording is never stopped
+    fn drop(&mut self) {
+        let _ = self.end();
+    }
+}
+
+fn write_sizes<W: Write + Seek>(sink: &mut W, data_size: u32) -> io::Result<()> {
+    sink.seek(SeekFrom::Start(RIFF_SIZE_OFFSET))?;
+    sink.write_all(&data_size.saturating_add(36).to_le_bytes())?;
+    sink.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+    sink.write_all(&data_size.to_le_bytes())?;
+    sink.seek(SeekFrom::End(0))?;
+    Ok(())
+}
+
+/// A `WavRecorder` with its sink type erased, so seekable and streamed recordings can be kept
+/// in the same place
+pub trait WavOutput {
+    fn push(&mut self, samples: &[f32]);
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+impl<W: Write> WavOutput for WavRecorder<W> {
+    fn push(&mut self, samples: &[f32]) {
+        WavRecorder::push(self, samples);
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        WavRecorder::finish(*self).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WavRecorder;
+    use std::io::Cursor;
+
+    fn check_wav(wav: &[u8], data_size: u32) {
+        assert_eq!(wav.len(), 44 + 3 * 2);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[4..8], &data_size.saturating_add(36).to_le_bytes());
+        assert_eq!(&wav[24..28], &44_100u32.to_le_bytes());
+        assert_eq!(&wav[40..44], &data_size.to_le_bytes());
+        assert_eq!(&wav[44..], &[0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80]);
+    }
+
+    #[test]
+    fn wav_header() {
+        let mut recorder = WavRecorder::seekable(Cursor::new(Vec::new()), 44_100).unwrap();
+        recorder.push(&[0.0, 1.0]);
+        recorder.push(&[-1.0]);
+        let wav = recorder.finish().unwrap().into_inner();
+
+        check_wav(&wav, 6);
+    }
+
+    #[test]
+    fn wav_stream() {
+        let mut recorder = WavRecorder::new(Vec::new(), 44_100).unwrap();
+        recorder.push(&[0.0, 1.0, -1.0]);
+        let wav = recorder.finish().unwrap();
+
+        check_wav(&wav, 0xFFFF_FFFF);
+    }
+
+    #[test]
+    fn wav_finished_on_drop() {
+        let mut wav = Vec::new();
+        {
+            let mut recorder = WavRecorder::seekable(Cursor::new(&mut wav), 44_100).unwrap();
+            recorder.push(&[0.0, 1.0, -1.0]);
+        }
+
+        check_wav(&wav, 6);
+    }
+}
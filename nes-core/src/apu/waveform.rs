@@ -0,0 +1,49 @@
+/// Ring buffer holding the most recent output levels of a channel, meant for oscilloscope views.
+pub struct WaveformTap {
+    samples: Vec<f32>,
+    position: usize,
+}
+
+impl WaveformTap {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: vec![0.0; capacity],
+            position: 0,
+        }
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        self.samples[self.position] = sample;
+        self.position = (self.position + 1) % self.samples.len();
+    }
+
+    /// Copies the most recent samples into `buf`, oldest first, returning how many were written.
+    pub fn read(&self, buf: &mut [f32]) -> usize {
+        let count = buf.len().min(self.samples.len());
+        let start = self.position + self.samples.len() - count;
+
+        for (i, sample) in buf.iter_mut().take(count).enumerate() {
+            *sample = self.samples[(start + i) % self.samples.len()];
+        }
+
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WaveformTap;
+
+    #[test]
+    fn wraps_around() {
+        let mut tap = WaveformTap::new(4);
+        let mut buf = [0f32; 3];
+
+        for i in 0..6 {
+            tap.push(i as f32);
+        }
+
+        assert_eq!(tap.read(&mut buf), 3);
+        assert_eq!(buf, [3.0, 4.0, 5.0]);
+    }
+}
@@ -1,7 +1,9 @@
+use crate::apu::Apu;
 use crate::input::InputBus;
 use crate::input::InputType;
 use crate::ppu::Ppu;
 use crate::rom::mapper::Mapper;
+use crate::rom::rom_file::RomError;
 use crate::rom::rom_file::RomFile;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -19,8 +21,13 @@ pub struct DataBus {
     ram: [u8; RAM_SIZE],
     pub mapper: Option<Rc<RefCell<Box<dyn Mapper>>>>,
     pub ppu: Option<Rc<RefCell<Ppu>>>,
+    pub apu: Option<Rc<RefCell<Apu>>>,
     pub input0: InputType,
     pub input1: InputType,
+    /// Whether the cartridge keeps its memory with a battery, from the ROM header
+    pub battery: bool,
+    /// Address of the last CPU read, used to emulate DMA conflicts.
+    last_read_address: u16,
 }
 
 impl DataBus {
@@ -29,20 +36,30 @@ impl DataBus {
             ram: [0; RAM_SIZE],
             mapper: None,
             ppu: None,
+            apu: None,
             input0: InputType::Disconnected,
             input1: InputType::Disconnected,
+            battery: false,
+            last_read_address: 0,
         }
     }
 
-    pub fn connect_cartridge(&mut self, mut rom: RomFile) {
-        let b = Rc::new(RefCell::new(rom.get_mapper()));
+    /// Keeps the current cartridge when the mapper of the new one isn't supported
+    pub fn connect_cartridge(&mut self, mut rom: RomFile) -> Result<(), RomError> {
+        let b = Rc::new(RefCell::new(rom.get_mapper()?));
         self.mapper = Some(b.clone());
-        self.ppu.as_mut().unwrap().borrow_mut().mapper = Some(b.clone());
+        self.battery = rom.header.has_battery();
+        let mut ppu = self.ppu.as_mut().unwrap().borrow_mut();
+        ppu.mirroring = rom.header.get_mirroring();
+        ppu.mapper = Some(b.clone());
+        Ok(())
     }
 
     /// https://wiki.nesdev.com/w/index.php/CPU_memory_map
     #[allow(clippy::match_overlapping_arm)]
     pub fn read(&mut self, address: u16) -> u8 {
+        self.last_read_address = address;
+
         match address {
             0x0000..=0x1FFF => self.ram[address as usize % RAM_SIZE],
             0x2000..=0x3FFF => self
@@ -52,6 +69,12 @@ impl DataBus {
                 .borrow_mut()
                 .read_register_cpu_address(repeat_every(address, 0x2000, 8)),
             0x4014 => 0, // OAMDMA $4014 is write only!
+            0x4015 => self
+                .apu
+                .as_ref()
+                .unwrap()
+                .borrow_mut()
+                .read_register_cpu_address(address),
             0x4016 => self.input0.output(),
             0x4017 => self.input1.output(),
             0x4000..=0x401F => 0, // APU and IO registers
@@ -65,17 +88,56 @@ impl DataBus {
         }
     }
 
+    /// Reads memory without side effects, for debuggers. I/O registers read as 0.
+    pub fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x1FFF => self.ram[address as usize % RAM_SIZE],
+            0x2000..=0x401F => 0,
+            0x4020..=0xFFFF => {
+                if let Some(mapper) = self.mapper.as_ref() {
+                    mapper.borrow().peek_prg(address)
+                } else {
+                    0
+                }
+            }
+        }
+    }
+
+    /// Sample fetch done by the DMC's DMA unit. If the DMA halts the CPU while it is reading a
+    /// controller port, the repeated read clocks the controller an extra time and a bit is lost.
+    /// https://wiki.nesdev.com/w/index.php/APU_DMC#Conflict_with_controller_and_PPU_read
+    pub fn dmc_dma_read(&mut self, address: u16, cpu_reading: bool) -> u8 {
+        if cpu_reading {
+            match self.last_read_address {
+                0x4016 => {
+                    self.input0.output();
+                }
+                0x4017 => {
+                    self.input1.output();
+                }
+                _ => {}
+            }
+        }
+
+        self.read(address)
+    }
+
     /// https://wiki.nesdev.com/w/index.php/CPU_memory_map
     #[allow(clippy::match_overlapping_arm)]
     pub fn write(&mut self, address: u16, value: u8) {
         match address {
             0x0000..=0x1FFF => self.ram[address as usize % RAM_SIZE] = value,
-            0x2000..=0x3FFF => self
-                .ppu
-                .as_ref()
-                .unwrap()
-                .borrow_mut()
-                .write_register_cpu_address(repeat_every(address, 0x2000, 8), value),
+            0x2000..=0x3FFF => {
+                let address = repeat_every(address, 0x2000, 8);
+                self.ppu
+                    .as_ref()
+                    .unwrap()
+                    .borrow_mut()
+                    .write_register_cpu_address(address, value);
+                if let Some(mapper) = self.mapper.as_mut() {
+                    mapper.borrow_mut().ppu_register_write(address, value);
+                }
+            }
             0x4014 => {
                 for i in 0..=255 {
                     let v = self.read(((value as u16) << 8) + i as u16);
@@ -84,11 +146,16 @@ impl DataBus {
                     ppu.oam_memory[unchecked_add!(i, oam_addr) as usize % 256] = v;
                 }
             }
+            0x4000..=0x4013 | 0x4015 | 0x4017 => self
+                .apu
+                .as_ref()
+                .unwrap()
+                .borrow_mut()
+                .write_register_cpu_address(address, value),
             0x4016 => {
                 self.input0.input(value);
                 self.input1.input(value);
             }
-            0x4017 => {}
             0x4000..=0x401F => {} // APU and IO registers
             0x4020..=0xFFFF => {
                 if let Some(mapper) = self.mapper.as_mut() {
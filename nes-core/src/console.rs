@@ -1,6 +1,12 @@
 extern crate gif;
 extern crate png;
 
+use crate::apu::filter::AudioFilter;
+use crate::apu::recording_in_progress;
+use crate::apu::Apu;
+use crate::apu::AudioChannel;
+use crate::apu::WavOutput;
+use crate::apu::WavRecorder;
 use crate::bus::DataBus;
 use crate::cpu::CPU6502;
 use crate::palette;
@@ -11,7 +17,10 @@ use gif::{Encoder, Frame, Repeat};
 use std::borrow::Cow;
 use std::cell::RefCell;
 use std::fs::File;
+use std::io;
 use std::io::BufWriter;
+use std::io::ErrorKind;
+use std::io::Seek;
 use std::io::Write;
 use std::path::Path;
 use std::rc::Rc;
@@ -20,10 +29,15 @@ use std::u16;
 pub const NES_WIDTH: u32 = 256;
 pub const NES_HEIGHT: u32 = 240;
 
+/// CPU cycles taken by a DMC sample fetch.
+/// https://wiki.nesdev.com/w/index.php/APU_DMC#Memory_reader
+const DMC_DMA_CYCLES: u64 = 4;
+
 pub struct NesConsole {
     pub cpu: Rc<RefCell<CPU6502>>,
     pub bus: Rc<RefCell<DataBus>>,
     pub ppu: Rc<RefCell<Ppu>>,
+    pub apu: Rc<RefCell<Apu>>,
 }
 
 impl NesConsole {
@@ -31,13 +45,15 @@ impl NesConsole {
         let bus = Rc::new(RefCell::new(DataBus::new()));
         let cpu = Rc::new(RefCell::new(CPU6502::new(bus.clone())));
         let ppu = Rc::new(RefCell::new(Ppu::new(cpu.clone())));
+        let apu = Rc::new(RefCell::new(Apu::new()));
 
         {
             let mut bus = bus.borrow_mut();
             bus.ppu = Some(ppu.clone());
+            bus.apu = Some(apu.clone());
         }
 
-        NesConsole { bus, cpu, ppu }
+        NesConsole { bus, cpu, ppu, apu }
     }
 
     pub fn reset(&mut self) {
@@ -54,12 +70,51 @@ impl NesConsole {
     }
 
     pub fn tick(&mut self) {
-        let mut l = self.cpu.borrow().ticks;
+        let mut cycle = self.cpu.borrow().ticks;
         self.cpu.borrow_mut().process_next_opcode();
-        l = self.cpu.borrow().ticks - l;
+        let instruction_end = self.cpu.borrow().ticks;
 
-        for _ in 0..l * 3 {
-            self.ppu.borrow_mut().tick();
+        // DMA stalls keep pushing the CPU tick count while the other components catch up
+        while cycle < self.cpu.borrow().ticks {
+            self.tick_cycle(cycle + 1 == instruction_end);
+            cycle += 1;
+        }
+
+        let mapper_irq = match self.bus.borrow().mapper.as_ref() {
+            Some(mapper) => mapper.borrow().irq_pending(),
+            None => false,
+        };
+        let irq = self.apu.borrow().irq_pending() || mapper_irq;
+        self.cpu.borrow_mut().set_irq_line(irq);
+    }
+
+    /// Runs the other components for one CPU cycle. `last_cycle` tells whether this is the final
+    /// cycle of the current instruction, where the CPU does its operand read.
+    fn tick_cycle(&mut self, last_cycle: bool) {
+        {
+            let mut ppu = self.ppu.borrow_mut();
+            for _ in 0..3 {
+                ppu.tick();
+            }
+        }
+
+        let expansion = match self.bus.borrow().mapper.as_ref() {
+            Some(mapper) => {
+                let mut mapper = mapper.borrow_mut();
+                mapper.clock();
+                mapper.audio_output()
+            }
+            None => 0.0,
+        };
+
+        let mut apu = self.apu.borrow_mut();
+        apu.set_expansion_output(expansion);
+        apu.tick();
+
+        if let Some(address) = apu.dmc_dma_request() {
+            let value = self.bus.borrow_mut().dmc_dma_read(address, last_cycle);
+            apu.dmc_dma_complete(value);
+            self.cpu.borrow_mut().stall(DMC_DMA_CYCLES);
         }
     }
 
@@ -126,6 +181,143 @@ impl NesConsole {
         }
     }
 
+    /// Sets the sample rate of the audio returned by `get_audio_output_*`.
+    pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        self.apu.borrow_mut().set_sample_rate(sample_rate);
+    }
+
+    pub fn audio_samples_available(&self) -> usize {
+        self.apu.borrow().samples_available()
+    }
+
+    /// Toggles one of the hardware output filters, all of them are enabled by default.
+    pub fn set_audio_filter(&mut self, filter: AudioFilter, enabled: bool) {
+        self.apu.borrow_mut().set_filter_enabled(filter, enabled);
+    }
+
+    /// Replaces the hardware output filters with a plain DC blocker.
+    pub fn set_audio_clean_mode(&mut self, clean_mode: bool) {
+        self.apu.borrow_mut().set_clean_mode(clean_mode);
+    }
+
+    pub fn set_audio_channel_muted(&mut self, channel: AudioChannel, muted: bool) {
+        self.apu.borrow_mut().set_channel_muted(channel, muted);
+    }
+
+    /// While any channel is soloed, only the soloed channels are heard.
+    pub fn set_audio_channel_soloed(&mut self, channel: AudioChannel, soloed: bool) {
+        self.apu.borrow_mut().set_channel_soloed(channel, soloed);
+    }
+
+    /// Copies the most recent output levels (0.0 to 1.0) of a channel into `buf`, oldest first,
+    /// returning the number of samples written. Meant for oscilloscope views.
+    pub fn get_audio_channel_waveform(&self, channel: AudioChannel, buf: &mut [f32]) -> usize {
+        self.apu.borrow().read_waveform(channel, buf)
+    }
+
+    /// Pulls the audio generated so far into `buf`, returning the number of samples written.
+    /// Samples are mono, between -1.0 and 1.0.
+    pub fn get_audio_output_f32(&self, buf: &mut [f32]) -> usize {
+        self.apu.borrow_mut().read_samples(buf)
+    }
+
+    /// Same as `get_audio_output_f32`, with samples converted to signed 16 bits.
+    pub fn get_audio_output_i16(&self, buf: &mut [i16]) -> usize {
+        let mut samples = vec![0f32; buf.len()];
+        let count = self.get_audio_output_f32(&mut samples);
+
+        for i in 0..count {
+            buf[i] = (samples[i].clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        }
+
+        count
+    }
+
+    /// Starts recording the audio output into a WAV file as it's generated, whether or not it's
+    /// read through `get_audio_output_*`. Fails without touching the file if a recording is
+    /// already in progress.
+    pub fn start_wav_recording(&mut self, path: &Path) -> io::Result<()> {
+        if self.is_recording_wav() {
+            return Err(recording_in_progress());
+        }
+        let file = File::create(path)?;
+        self.start_wav_recording_to_seekable(BufWriter::new(file))
+    }
+
+    /// Starts recording the audio output as WAV data into any sink, like a pipe or a socket. The
+    /// header sizes are left unknown, as the sink can't be rewound to fill them in.
+    pub fn start_wav_recording_to<W: Write + 'static>(&mut self, sink: W) -> io::Result<()> {
+        self.start_recording(|sample_rate| Ok(Box::new(WavRecorder::new(sink, sample_rate)?)))
+    }
+
+    /// Starts recording the audio output as WAV data into a seekable sink, which gets the header
+    /// sizes filled in once the recording is stopped, or when the console is dropped.
+    pub fn start_wav_recording_to_seekable<W: Write + Seek + 'static>(
+        &mut self,
+        sink: W,
+    ) -> io::Result<()> {
+        self.start_recording(|sample_rate| Ok(Box::new(WavRecorder::seekable(sink, sample_rate)?)))
+    }
+
+    fn start_recording(
+        &mut self,
+        recorder: impl FnOnce(u32) -> io::Result<Box<dyn WavOutput>>,
+    ) -> io::Result<()> {
+        if self.is_recording_wav() {
+            return Err(recording_in_progress());
+        }
+        let mut apu = self.apu.borrow_mut();
+        let output = recorder(apu.sample_rate())?;
+        apu.start_recording(output)
+    }
+
+    pub fn stop_wav_recording(&mut self) -> io::Result<()> {
+        self.apu.borrow_mut().stop_recording()
+    }
+
+    pub fn is_recording_wav(&self) -> bool {
+        self.apu.borrow().is_recording()
+    }
+
+    /// Battery-backed memory of the cartridge, `None` when there's no cartridge or no battery.
+    pub fn get_save_data(&self) -> Option<Vec<u8>> {
+        let bus = self.bus.borrow();
+        if !bus.battery {
+            return None;
+        }
+
+        let data = bus.mapper.as_ref()?.borrow().save_data();
+        data
+    }
+
+    /// Restores the battery-backed memory of the cartridge from bytes taken with `get_save_data`.
+    pub fn set_save_data(&mut self, data: &[u8]) {
+        if let Some(mapper) = self.bus.borrow().mapper.as_ref() {
+            mapper.borrow_mut().load_save_data(data);
+        }
+    }
+
+    /// Restores the battery-backed memory from a save file, a missing file leaves it untouched.
+    pub fn load_save_file(&mut self, path: &Path) -> io::Result<()> {
+        match std::fs::read(path) {
+            Ok(data) => {
+                self.set_save_data(&data);
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the battery-backed memory to a save file, cartridges without a battery write
+    /// nothing.
+    pub fn flush_save_file(&self, path: &Path) -> io::Result<()> {
+        match self.get_save_data() {
+            Some(data) => std::fs::write(path, data),
+            None => Ok(()),
+        }
+    }
+
     pub fn screenshot(&self, path: &str) {
         let file = File::create(Path::new(path)).unwrap();
         let ref mut w = BufWriter::new(file);
@@ -162,3 +354,11 @@ impl NesConsole {
         encoder.write_frame(&frame).unwrap();
     }
 }
+
+impl Drop for NesConsole {
+    /// The components hold each other through `Rc`s and are never dropped, so the recording has
+    /// to be finished here for its file to be complete
+    fn drop(&mut self) {
+        let _ = self.stop_wav_recording();
+    }
+}
@@ -399,4 +399,15 @@ impl CPU6502 {
     pub fn request_irq(&mut self) {
         self.irq_requested = true;
     }
+
+    /// Drive the IRQ line, interrupts are level triggered and only serviced while the line is
+    /// asserted and the I flag is clear.
+    pub fn set_irq_line(&mut self, asserted: bool) {
+        self.irq_requested = asserted;
+    }
+
+    /// Halt the CPU for the given number of cycles, as done by DMA transfers.
+    pub fn stall(&mut self, cycles: u64) {
+        self.ticks += cycles;
+    }
 }
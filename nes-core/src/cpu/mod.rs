@@ -51,7 +51,7 @@ impl CPU6502 {
 
     #[allow(clippy::cast_lossless)]
     pub fn process_next_opcode(&mut self) -> (u8, u8, u8) {
-        if self.irq_requested {
+        if self.irq_requested && !self.get_flag(SRFlag::InterruptDisable) {
             self.irq();
             return (0, 0, 0);
         }
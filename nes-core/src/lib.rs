@@ -4,6 +4,7 @@ extern crate bitflags;
 #[macro_use]
 mod macros;
 
+pub mod apu;
 pub mod bus;
 pub mod console;
 pub mod cpu;
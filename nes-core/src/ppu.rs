@@ -2,6 +2,7 @@ use crate::console::NES_HEIGHT;
 use crate::console::NES_WIDTH;
 use crate::cpu::CPU6502;
 use crate::rom::mapper::Mapper;
+use crate::rom::mapper::Mirroring;
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -105,7 +106,12 @@ pub struct Ppu {
 
     pub output: [u8; (NES_WIDTH * NES_HEIGHT) as usize], // 256x240 pixels
     bitmap: [u8; (NES_WIDTH * NES_HEIGHT) as usize],     // 256x240 pixels
-    vram: [u8; 0x1000], // 2kb // TODO: Implement mirroring and fix the VRAM size
+    /// 2KB of console VRAM holding two nametables
+    ciram: [u8; 0x800],
+    /// Extra 2KB of nametable memory found on four-screen boards
+    four_screen_vram: [u8; 0x800],
+    /// Nametable arrangement from the ROM header, used unless the mapper picks one
+    pub mirroring: Mirroring,
     pub palette_vram: [u8; 32],
     vram_buffer: u8,
 
@@ -138,6 +144,8 @@ pub struct Ppu {
     sprite_at: [u8; 8],
     /// 8 counters - These contain the X positions for up to 8 sprites.
     sprite_x_pos: [i16; 8],
+    /// Pattern table address of the row of each sprite fetched during dots 257-320
+    sprite_addresses: [u16; 8],
     // #endregion
     sprite_count: u8,
 
@@ -160,7 +168,9 @@ impl Ppu {
             w: false,
             output: [0; (NES_WIDTH * NES_HEIGHT) as usize],
             bitmap: [0; (NES_WIDTH * NES_HEIGHT) as usize],
-            vram: [0; 0x1000],
+            ciram: [0; 0x800],
+            four_screen_vram: [0; 0x800],
+            mirroring: Mirroring::Horizontal,
             palette_vram: [0; 32],
             vram_buffer: 0,
             dot: 0,
@@ -179,6 +189,7 @@ impl Ppu {
             sprite_pattern_hi: [0; 8],
             sprite_at: [0; 8],
             sprite_x_pos: [0; 8],
+            sprite_addresses: [0; 8],
             sprite_count: 0,
             cpu,
             mapper: None,
@@ -187,7 +198,9 @@ impl Ppu {
     }
 
     pub fn read_vram(&mut self, mut addr: u16) -> u8 {
-        match addr {
+        let bus_address = addr;
+
+        let value = match addr {
             0x0000..=0x1FFF => {
                 if let Some(mapper) = self.mapper.as_mut() {
                     mapper.borrow_mut().read_chr(addr)
@@ -195,8 +208,17 @@ impl Ppu {
                     0
                 }
             }
-            0x2000..=0x2FFF => self.vram[(addr - 0x2000) as usize],
-            0x3000..=0x3EFF => self.vram[(addr - 0x3000) as usize], // Mirrors of $2000-$2EFF
+            0x2000..=0x3EFF => {
+                let addr = 0x2000 | (addr & 0x0FFF); // $3000-$3EFF mirrors $2000-$2EFF
+                let cartridge = match self.mapper.as_ref() {
+                    Some(mapper) => mapper.borrow().read_nametable(addr),
+                    None => None,
+                };
+                match cartridge {
+                    Some(value) => value,
+                    None => *self.nametable(addr),
+                }
+            }
             0x3F00..=0x3FFF => {
                 // Palette RAM indexes
                 if addr % 4 == 0 {
@@ -205,18 +227,32 @@ impl Ppu {
                 self.palette_vram[addr as usize & 0x1F]
             }
             0x4000..=0xFFFF => unreachable!(),
-        }
+        };
+
+        // Latches on the cartridge switch once the fetch is done
+        self.notify_mapper(bus_address);
+        value
     }
 
     pub fn write_vram(&mut self, mut addr: u16, value: u8) {
+        self.notify_mapper(addr);
+
         match addr {
             0x0000..=0x1FFF => {
                 if let Some(mapper) = self.mapper.as_mut() {
                     mapper.borrow_mut().write_chr(addr, value);
                 }
             }
-            0x2000..=0x2FFF => self.vram[(addr - 0x2000) as usize] = value,
-            0x3000..=0x3EFF => self.vram[(addr - 0x3000) as usize] = value, // Mirrors of $2000-$2EFF
+            0x2000..=0x3EFF => {
+                let addr = 0x2000 | (addr & 0x0FFF); // $3000-$3EFF mirrors $2000-$2EFF
+                let cartridge = match self.mapper.as_mut() {
+                    Some(mapper) => mapper.borrow_mut().write_nametable(addr, value),
+                    None => false,
+                };
+                if !cartridge {
+                    *self.nametable(addr) = value;
+                }
+            }
             0x3F00..=0x3FFF => {
                 // Palette RAM indexes
                 if addr % 4 == 0 {
@@ -228,6 +264,42 @@ impl Ppu {
         }
     }
 
+    /// https://wiki.nesdev.com/w/index.php/Mirroring#Nametable_Mirroring
+    fn nametable(&mut self, addr: u16) -> &mut u8 {
+        let mirroring = match self.mapper.as_ref() {
+            Some(mapper) => mapper.borrow().mirroring(),
+            None => None,
+        }
+        .unwrap_or(self.mirroring);
+
+        let offset = (addr & 0x03FF) as usize;
+        match mirroring.nametable_page(addr) {
+            page @ 0..=1 => &mut self.ciram[page * 0x400 + offset],
+            page => &mut self.four_screen_vram[(page - 2) * 0x400 + offset],
+        }
+    }
+
+    /// Lets the mapper snoop the PPU address bus, palette accesses never leave the PPU
+    fn notify_mapper(&mut self, addr: u16) {
+        if addr < 0x3F00 {
+            if let Some(mapper) = self.mapper.as_mut() {
+                mapper.borrow_mut().ppu_address(addr);
+            }
+        }
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/PPU_OAM#Byte_1
+    fn sprite_pattern_address(&self, tile: u8, row: u16) -> u16 {
+        if self.ppuctrl.sprite_height() == 16 {
+            // 8x16 sprites take the pattern table from bit 0 of the tile number
+            let table = (tile as u16 & 0x01) << 12;
+            let tile = (tile as u16 & 0xFE) + (row >> 3);
+            table | (tile * 16) | (row & 0x07)
+        } else {
+            self.ppuctrl.sprite_tile_select() | (tile as u16 * 16) | row
+        }
+    }
+
     /// https://wiki.nesdev.com/w/index.php/PPU_sprite_evaluation
     /// Based off on https://github.com/ulfalizer/nesalizer/blob/master/src/ppu.cpp
     fn sprite_evaluation(&mut self) {
@@ -249,38 +321,65 @@ impl Ppu {
             let mut offset = y_pos as i16 - sprite_y_top as i16;
 
             let sprite_height = self.ppuctrl.sprite_height() as i16;
-            let sprite_tile_select = self.ppuctrl.sprite_tile_select();
 
             // If this sprite is on the next scanline, copy it to the _sprites array for rendering
             if offset < sprite_height && offset >= 0 {
                 if sprite_count == 8 {
                     // sprite_overflow = 1;
                 } else {
-                    let sprite_idx = self.oam_memory[i + 1] as u16;
+                    let sprite_idx = self.oam_memory[i + 1];
                     self.sprite_at[sprite_count] = self.oam_memory[i + 2];
 
                     // Flip vertically
                     if (self.sprite_at[sprite_count] & 0x80) != 0 {
-                        offset = 7 - offset;
+                        offset = sprite_height - 1 - offset;
                     }
 
-                    let addr = (sprite_idx * 16 + offset as u16) | sprite_tile_select;
-                    let mut pattern_lo = self.read_vram(addr);
-                    let mut pattern_hi = self.read_vram(addr + 8);
+                    self.sprite_addresses[sprite_count] =
+                        self.sprite_pattern_address(sprite_idx, offset as u16);
                     self.sprite_x_pos[sprite_count] = self.oam_memory[i + 3] as i16;
 
-                    // Flip horizontally
-                    if (self.sprite_at[sprite_count] & 0x40) != 0 {
-                        pattern_lo = reverse_bits(pattern_lo);
-                        pattern_hi = reverse_bits(pattern_hi);
-                    }
+                    sprite_count += 1;
+                }
+            }
+        }
 
-                    self.sprite_pattern_lo[sprite_count] = pattern_lo;
-                    self.sprite_pattern_hi[sprite_count] = pattern_hi;
+        // Unused sprite slots still fetch tile $FF, which mappers watching A12 rely on
+        for i in sprite_count..8 {
+            self.sprite_addresses[i] = self.sprite_pattern_address(0xFF, 0);
+        }
+        self.sprite_count = sprite_count as u8;
+    }
 
-                    sprite_count += 1;
+    /// Each sprite slot takes 8 dots: two garbage nametable fetches and the two pattern bytes.
+    /// https://wiki.nesdev.com/w/index.php/PPU_rendering#Cycles_257-320
+    fn sprite_fetch(&mut self) {
+        let slot = ((self.dot - 257) / 8) as usize;
+        let addr = self.sprite_addresses[slot];
+
+        match (self.dot - 257) % 8 {
+            0 | 2 => {
+                self.read_vram(0x2000 | (self.v & 0x0FFF));
+            }
+            4 => self.sprite_pattern_lo[slot] = self.read_vram(addr),
+            6 => {
+                let mut pattern_lo = self.sprite_pattern_lo[slot];
+                let mut pattern_hi = self.read_vram(addr + 8);
+
+                if slot >= self.sprite_count as usize {
+                    // Unused sprites are loaded with an all-transparent bitmap
+                    pattern_lo = 0;
+                    pattern_hi = 0;
+                } else if (self.sprite_at[slot] & 0x40) != 0 {
+                    // Flip horizontally
+                    pattern_lo = reverse_bits(pattern_lo);
+                    pattern_hi = reverse_bits(pattern_hi);
                 }
+
+                self.sprite_pattern_lo[slot] = pattern_lo;
+                self.sprite_pattern_hi[slot] = pattern_hi;
             }
+            _ => {}
         }
     }
 
@@ -410,7 +509,8 @@ impl Ppu {
 
         self.dot += 1;
 
-        if self.dot > 341 {
+        // Scanlines are 341 dots long, 0 to 340
+        if self.dot > 340 {
             self.dot = 0;
             self.scanline += 1;
         }
@@ -464,6 +564,12 @@ impl Ppu {
                         self.nt_latch = self.read_vram(0x2000 | (self.v & 0x0FFF));
                     }
 
+                    3 if self.dot == 339 => {
+                        // Unused nametable fetch, the last one of the scanline. The MMC5 relies on it
+                        // to detect scanlines.
+                        self.read_vram(0x2000 | (self.v & 0x0FFF));
+                    }
+
                     3 => {
                         // Fetch the corresponding attribute table entry from $23C0-$2FFF and increment the current VRAM address within the same row.
                         // https://wiki.nesdev.com/w/index.php/PPU_scrolling#Tile_and_attribute_fetching
@@ -511,7 +617,7 @@ impl Ppu {
                         self.sprite_count = 0;
                     }
                     65..=256 => self.sprite_evaluation(),
-                    257..=320 => {}     // Sprite fetches
+                    257..=320 => self.sprite_fetch(),
                     321..=340 | 0 => {} //  Background render pipeline initialization
                     _ => {}
                 }
@@ -1,6 +1,140 @@
+/// Nametable arrangement, selecting which 1KB page backs each of the four PPU nametables.
+/// https://wiki.nesdev.com/w/index.php/Mirroring#Nametable_Mirroring
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mirroring {
+    /// $2000 equals $2400 and $2800 equals $2C00 (vertical arrangement)
+    Horizontal,
+    /// $2000 equals $2800 and $2400 equals $2C00 (horizontal arrangement)
+    Vertical,
+    /// All nametables use the first CIRAM page
+    SingleScreenLower,
+    /// All nametables use the second CIRAM page
+    SingleScreenUpper,
+    /// Each nametable has its own page, the last two come from memory on the cartridge
+    FourScreen,
+    /// Page picked for each nametable by the mapper
+    Custom([u8; 4]),
+}
+
+impl Mirroring {
+    /// Page (0-3) backing the nametable address, pages 0 and 1 are the console's CIRAM
+    pub fn nametable_page(self, addr: u16) -> usize {
+        let table = ((addr >> 10) & 0x03) as usize;
+        match self {
+            Mirroring::Horizontal => table >> 1,
+            Mirroring::Vertical => table & 0x01,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+            Mirroring::FourScreen => table,
+            Mirroring::Custom(pages) => pages[table] as usize,
+        }
+    }
+}
+
+/// Cartridge board interface. Only the four memory accessors are required, the other hooks
+/// have defaults that suit simple boards like NROM.
 pub trait Mapper {
     fn read_prg(&self, addr: u16) -> u8;
     fn write_prg(&mut self, addr: u16, value: u8);
     fn read_chr(&self, addr: u16) -> u8;
     fn write_chr(&mut self, addr: u16, value: u8);
+
+    /// Reads PRG space without side effects, for debuggers
+    fn peek_prg(&self, addr: u16) -> u8 {
+        self.read_prg(addr)
+    }
+
+    /// Reads CHR space without side effects, for debuggers
+    fn peek_chr(&self, addr: u16) -> u8 {
+        self.read_chr(addr)
+    }
+
+    /// Called once every CPU cycle, for boards with cycle counters
+    fn clock(&mut self) {}
+
+    /// Whether the cartridge is pulling the CPU IRQ line low
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    /// Called for CPU writes to the PPU registers ($2000-$2007), for boards that watch them
+    fn ppu_register_write(&mut self, _addr: u16, _value: u8) {}
+
+    /// Called with every address the PPU puts on its bus while accessing $0000-$3EFF, after
+    /// the data has been transferred
+    fn ppu_address(&mut self, _addr: u16) {}
+
+    /// Nametable arrangement picked by the board, `None` uses the one from the ROM header
+    fn mirroring(&self) -> Option<Mirroring> {
+        None
+    }
+
+    /// Lets boards with their own nametable memory answer PPU reads of $2000-$2FFF.
+    /// `None` leaves the access to the console's CIRAM.
+    fn read_nametable(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    /// Lets boards with their own nametable memory take PPU writes to $2000-$2FFF.
+    /// Returns `false` to leave the access to the console's CIRAM.
+    fn write_nametable(&mut self, _addr: u16, _value: u8) -> bool {
+        false
+    }
+
+    /// Amplitude of the cartridge's expansion audio, on the same scale as the 2A03 mix
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+
+    /// PRG RAM at $6000-$7FFF, `None` for boards without it
+    fn prg_ram(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+
+    /// Contents of the memory a battery would keep, the PRG RAM unless the board has more.
+    /// Only saved for cartridges with a battery.
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.prg_ram().map(|prg_ram| prg_ram.to_vec())
+    }
+
+    /// Restores battery-backed memory from the bytes returned by `save_data`
+    fn load_save_data(&mut self, data: &[u8]) {
+        if let Some(prg_ram) = self.prg_ram_mut() {
+            let size = prg_ram.len().min(data.len());
+            prg_ram[..size].copy_from_slice(&data[..size]);
+        }
+    }
+}
+
+/// Value latched by a write to the ROM area of discrete logic boards with bus conflicts. The ROM
+/// drives the data bus at the same time as the CPU, so the register gets the written value ANDed
+/// with the ROM byte at that address.
+/// https://wiki.nesdev.com/w/index.php/Bus_conflict
+pub fn bus_conflict(mapper: &dyn Mapper, addr: u16, value: u8) -> u8 {
+    value & mapper.read_prg(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mirroring;
+
+    #[test]
+    fn nametable_pages() {
+        let pages = |mirroring: Mirroring| {
+            [0x2000, 0x2400, 0x2800, 0x2C00]
+                .iter()
+                .map(|&addr| mirroring.nametable_page(addr + 0x3FF))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(pages(Mirroring::Horizontal), [0, 0, 1, 1]);
+        assert_eq!(pages(Mirroring::Vertical), [0, 1, 0, 1]);
+        assert_eq!(pages(Mirroring::SingleScreenLower), [0, 0, 0, 0]);
+        assert_eq!(pages(Mirroring::SingleScreenUpper), [1, 1, 1, 1]);
+        assert_eq!(pages(Mirroring::FourScreen), [0, 1, 2, 3]);
+    }
 }
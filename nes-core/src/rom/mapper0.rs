@@ -1,4 +1,5 @@
 use crate::rom::mapper::Mapper;
+use crate::rom::rom_file::RomError;
 use crate::rom::rom_file::RomFile;
 use crate::rom::rom_header::RomHeader;
 
@@ -9,47 +10,52 @@ pub struct Mapper0 {
     pub header: RomHeader,
     pub pgr_data: Box<[u8]>,
     pub chr_data: Box<[u8]>,
+    pub prg_ram: Box<[u8]>,
 }
 
 impl Mapper0 {
-    pub fn new(rom: &mut RomFile) -> Self {
-        assert_eq!(
-            rom.header.chr_rom_size > 1,
-            false,
-            "NROM should have 8KB of CHR ROM or RAM only"
-        );
-
-        let chr_data = if rom.header.chr_rom_size == 0 {
-            vec![0u8; 0x4000].into_boxed_slice() // 8KB of CHR RAM
+    pub fn new(rom: &mut RomFile) -> Result<Self, RomError> {
+        // NROM has up to 32KB of PRG ROM and 8KB of CHR ROM or RAM only
+        let prg_rom = rom.pgr_data.len();
+        let chr_rom = rom.chr_data.len();
+        if prg_rom > 0x8000 || (chr_rom != 0 && chr_rom != 0x2000) {
+            return Err(RomError::InconsistentSizes { prg_rom, chr_rom });
+        }
+
+        let chr_data = if !rom.header.has_chr_rom() {
+            rom.chr_ram()
         } else {
             Box::from(rom.chr_data.as_ref()) // 8KB of CHR ROM
         };
 
-        Self {
+        Ok(Self {
             header: rom.header,
             pgr_data: Box::from(rom.pgr_data.as_ref()),
             chr_data,
-        }
+            prg_ram: rom.prg_ram(0x2000),
+        })
     }
 
-    fn rel_address(&self, address: u16) -> u16 {
-        match self.header.prg_rom_size {
-            1 => (address - 0x8000) % 0x4000,
-            _ => (address - 0x8000),
-        }
+    /// 16KB of PRG ROM are mirrored at $C000
+    fn rel_address(&self, address: u16) -> usize {
+        (address - 0x8000) as usize % self.pgr_data.len()
     }
 }
 
 impl Mapper for Mapper0 {
     fn read_prg(&self, addr: u16) -> u8 {
         match addr {
-            0x0000..=0x5FFF => unreachable!(),
-            0x6000..=0x7FFF => 0,
-            0x8000..=0xFFFF => self.pgr_data[self.rel_address(addr) as usize],
+            0x0000..=0x5FFF => 0,
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()],
+            0x8000..=0xFFFF => self.pgr_data[self.rel_address(addr)],
         }
     }
 
-    fn write_prg(&mut self, _addr: u16, _value: u8) {}
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()] = value;
+        }
+    }
 
     fn read_chr(&self, addr: u16) -> u8 {
         match addr {
@@ -59,7 +65,7 @@ impl Mapper for Mapper0 {
     }
 
     fn write_chr(&mut self, addr: u16, value: u8) {
-        if self.header.chr_rom_size != 0 {
+        if self.header.has_chr_rom() {
             println!("CHR RAM not available");
             return;
         }
@@ -69,4 +75,58 @@ impl Mapper for Mapper0 {
             _ => unreachable!(),
         };
     }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper0;
+    use crate::rom::mapper::Mapper;
+    use crate::rom::rom_file::RomFile;
+    use crate::rom::rom_header::RomHeader;
+
+    fn nrom() -> RomFile {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = 1;
+        header[5] = 1;
+        header[6] = 0x02; // Battery
+        RomFile {
+            header: RomHeader::new(&header),
+            pgr_data: vec![0u8; 0x4000].into_boxed_slice(),
+            chr_data: vec![0u8; 0x2000].into_boxed_slice(),
+            trainer: None,
+        }
+    }
+
+    #[test]
+    fn expansion_area_reads() {
+        let mapper = Mapper0::new(&mut nrom()).unwrap();
+        assert_eq!(mapper.read_prg(0x5000), 0);
+        assert_eq!(mapper.peek_prg(0x4020), 0);
+    }
+
+    #[test]
+    fn battery_backed_prg_ram() {
+        let mut rom = nrom();
+        let mut mapper = Mapper0::new(&mut rom).unwrap();
+
+        mapper.write_prg(0x6000, 0x12);
+        mapper.write_prg(0x7FFF, 0x34);
+        assert_eq!(mapper.read_prg(0x6000), 0x12);
+
+        let data = mapper.save_data().unwrap();
+        assert_eq!(data.len(), 0x2000);
+
+        let mut restored = Mapper0::new(&mut rom).unwrap();
+        restored.load_save_data(&data);
+        assert_eq!(restored.read_prg(0x7FFF), 0x34);
+    }
 }
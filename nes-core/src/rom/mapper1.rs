@@ -0,0 +1,244 @@
+use crate::rom::mapper::Mapper;
+use crate::rom::mapper::Mirroring;
+use crate::rom::rom_file::RomError;
+use crate::rom::rom_file::RomFile;
+use crate::rom::rom_header::RomHeader;
+
+/// The Nintendo MMC1 is a mapper ASIC used in Nintendo's SxROM and NES-EVENT Game Pak boards.
+/// The iNES format assigns mapper 1 to MMC1.
+/// https://wiki.nesdev.com/w/index.php/MMC1
+pub struct Mapper1 {
+    pub header: RomHeader,
+    pub pgr_data: Box<[u8]>,
+    pub chr_data: Box<[u8]>,
+    pub prg_ram: Box<[u8]>,
+    chr_ram: bool,
+
+    /// Serial port shift register, writes are shifted in from bit 4 down
+    shift_register: u8,
+    shift_count: u8,
+
+    /// Control register (internal, $8000-$9FFF) CPPMM
+    control: u8,
+    /// CHR bank 0 (internal, $A000-$BFFF)
+    chr_bank_0: u8,
+    /// CHR bank 1 (internal, $C000-$DFFF)
+    chr_bank_1: u8,
+    /// PRG bank (internal, $E000-$FFFF) RPPPP
+    prg_bank: u8,
+}
+
+impl Mapper1 {
+    pub fn new(rom: &mut RomFile) -> Result<Self, RomError> {
+        // The last 16KB bank can be fixed at $C000
+        if rom.pgr_data.len() < 0x4000 {
+            return Err(RomError::InconsistentSizes {
+                prg_rom: rom.pgr_data.len(),
+                chr_rom: rom.chr_data.len(),
+            });
+        }
+
+        let chr_ram = !rom.header.has_chr_rom();
+        let chr_data = if chr_ram {
+            rom.chr_ram()
+        } else {
+            Box::from(rom.chr_data.as_ref())
+        };
+
+        Ok(Self {
+            header: rom.header,
+            pgr_data: Box::from(rom.pgr_data.as_ref()),
+            chr_data,
+            prg_ram: rom.prg_ram(0x2000),
+            chr_ram,
+            shift_register: 0,
+            shift_count: 0,
+            control: 0x0C, // PRG ROM mode 3 on power-up, last bank fixed at $C000
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+        })
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        (self.prg_bank & 0x10) == 0
+    }
+
+    /// SUROM and SXROM boards use bit 4 of the CHR bank registers to select a 256KB PRG ROM half.
+    fn prg_outer_bank(&self) -> usize {
+        if self.pgr_data.len() > 0x40000 {
+            (self.chr_bank_0 as usize & 0x10) << 14
+        } else {
+            0
+        }
+    }
+
+    fn prg_address(&self, addr: u16) -> usize {
+        let bank = (self.prg_bank & 0x0F) as usize;
+        let last_bank = (self.pgr_data.len().min(0x40000) / 0x4000) - 1;
+        let offset = (addr & 0x3FFF) as usize;
+
+        let bank = match (self.control >> 2) & 0x03 {
+            // Switch 32KB at $8000, ignoring low bit of bank number
+            0 | 1 => (bank & !1) | ((addr as usize >> 14) & 1),
+            // Fix first bank at $8000 and switch 16KB bank at $C000
+            2 => {
+                if addr < 0xC000 {
+                    0
+                } else {
+                    bank
+                }
+            }
+            // Fix last bank at $C000 and switch 16KB bank at $8000
+            _ => {
+                if addr < 0xC000 {
+                    bank
+                } else {
+                    last_bank
+                }
+            }
+        };
+
+        (self.prg_outer_bank() | (bank * 0x4000 + offset)) % self.pgr_data.len()
+    }
+
+    fn chr_address(&self, addr: u16) -> usize {
+        let address = if (self.control & 0x10) == 0 {
+            // Switch 8KB at a time, ignoring low bit of bank number
+            (self.chr_bank_0 as usize & 0x1E) * 0x1000 + addr as usize
+        } else if addr < 0x1000 {
+            self.chr_bank_0 as usize * 0x1000 + addr as usize
+        } else {
+            self.chr_bank_1 as usize * 0x1000 + (addr as usize - 0x1000)
+        };
+
+        address % self.chr_data.len()
+    }
+
+    fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x8000..=0x9FFF => self.control = value,
+            0xA000..=0xBFFF => self.chr_bank_0 = value,
+            0xC000..=0xDFFF => self.chr_bank_1 = value,
+            0xE000..=0xFFFF => self.prg_bank = value,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Mapper for Mapper1 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x5FFF => 0,
+            0x6000..=0x7FFF => {
+                if self.prg_ram_enabled() {
+                    self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()]
+                } else {
+                    0
+                }
+            }
+            0x8000..=0xFFFF => self.pgr_data[self.prg_address(addr)],
+        }
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/MMC1#Registers
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x5FFF => {}
+            0x6000..=0x7FFF => {
+                if self.prg_ram_enabled() {
+                    self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()] = value;
+                }
+            }
+            0x8000..=0xFFFF => {
+                if (value & 0x80) != 0 {
+                    // Writing a value with bit 7 set clears the shift register
+                    self.shift_register = 0;
+                    self.shift_count = 0;
+                    self.control |= 0x0C;
+                    return;
+                }
+
+                self.shift_register = (self.shift_register >> 1) | ((value & 0x01) << 4);
+                self.shift_count += 1;
+
+                // On the fifth write, the address selects the internal register
+                if self.shift_count == 5 {
+                    self.write_register(addr, self.shift_register);
+                    self.shift_register = 0;
+                    self.shift_count = 0;
+                }
+            }
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr_data[self.chr_address(addr)]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if self.chr_ram {
+            let address = self.chr_address(addr);
+            self.chr_data[address] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(match self.control & 0x03 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        })
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper1;
+    use crate::rom::mapper::Mapper;
+    use crate::rom::rom_file::RomFile;
+    use crate::rom::rom_header::RomHeader;
+
+    fn serial_write(mapper: &mut Mapper1, addr: u16, value: u8) {
+        for i in 0..5 {
+            mapper.write_prg(addr, (value >> i) & 0x01);
+        }
+    }
+
+    #[test]
+    fn prg_banking() {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = 8; // 128KB PRG ROM
+        header[6] = 0x10;
+        let mut rom = RomFile {
+            header: RomHeader::new(&header),
+            pgr_data: (0..8 * 0x4000).map(|i| (i / 0x4000) as u8).collect(),
+            chr_data: Box::new([]),
+            trainer: None,
+        };
+        let mut mapper = Mapper1::new(&mut rom).unwrap();
+
+        // Power-up: last bank fixed at $C000
+        assert_eq!(mapper.read_prg(0x8000), 0);
+        assert_eq!(mapper.read_prg(0xC000), 7);
+
+        serial_write(&mut mapper, 0xE000, 3);
+        assert_eq!(mapper.read_prg(0x8000), 3);
+        assert_eq!(mapper.read_prg(0xFFFF), 7);
+
+        // 32KB mode
+        serial_write(&mut mapper, 0x8000, 0b00000);
+        assert_eq!(mapper.read_prg(0x8000), 2);
+        assert_eq!(mapper.read_prg(0xC000), 3);
+    }
+}
@@ -0,0 +1,63 @@
+use crate::rom::mapper::bus_conflict;
+use crate::rom::mapper::Mapper;
+use crate::rom::rom_file::RomFile;
+use crate::rom::rom_header::RomHeader;
+
+/// Color Dreams made unlicensed NES games, using its own board with a 74HC377 latch.
+/// The iNES format assigns mapper 11 to Color Dreams.
+/// https://wiki.nesdev.com/w/index.php/Color_Dreams
+pub struct Mapper11 {
+    pub header: RomHeader,
+    pub pgr_data: Box<[u8]>,
+    pub chr_data: Box<[u8]>,
+    /// CCCC LLPP
+    bank_select: u8,
+}
+
+impl Mapper11 {
+    pub fn new(rom: &mut RomFile) -> Self {
+        let chr_data = if !rom.header.has_chr_rom() {
+            rom.chr_ram()
+        } else {
+            Box::from(rom.chr_data.as_ref())
+        };
+
+        Self {
+            header: rom.header,
+            pgr_data: Box::from(rom.pgr_data.as_ref()),
+            chr_data,
+            bank_select: 0,
+        }
+    }
+}
+
+impl Mapper for Mapper11 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x7FFF => 0,
+            // CPU $8000-$FFFF: 32 KB switchable PRG ROM bank
+            0x8000..=0xFFFF => {
+                let bank = (self.bank_select & 0x03) as usize;
+                self.pgr_data[(bank * 0x8000 + (addr - 0x8000) as usize) % self.pgr_data.len()]
+            }
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        if addr >= 0x8000 {
+            self.bank_select = bus_conflict(self, addr, value);
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        // PPU $0000-$1FFF: 8 KB switchable CHR ROM bank
+        let bank = (self.bank_select >> 4) as usize;
+        self.chr_data[(bank * 0x2000 + addr as usize) % self.chr_data.len()]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if !self.header.has_chr_rom() {
+            self.chr_data[addr as usize] = value;
+        }
+    }
+}
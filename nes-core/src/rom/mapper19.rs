@@ -0,0 +1,309 @@
+use crate::apu::namco163::Namco163Audio;
+use crate::rom::mapper::Mapper;
+use crate::rom::mapper::Mirroring;
+use crate::rom::rom_file::RomFile;
+use crate::rom::rom_header::RomHeader;
+
+/// The Namco 163 is an ASIC mapper with 8KB PRG banking, 1KB CHR banking, nametables that can
+/// come from CHR ROM, a 15-bit CPU cycle IRQ counter and wavetable audio. The iNES format assigns
+/// mapper 19 to it. Pattern tables mapped to CIRAM are not emulated.
+/// https://wiki.nesdev.com/w/index.php/Namco_163
+pub struct Mapper19 {
+    pub header: RomHeader,
+    pub pgr_data: Box<[u8]>,
+    pub chr_data: Box<[u8]>,
+    pub prg_ram: Box<[u8]>,
+    chr_ram: bool,
+
+    /// CHR banks ($8000-$BFFF, one register every $800)
+    chr_banks: [u8; 8],
+    /// Nametable banks ($C000-$DFFF), $E0 and up select a CIRAM page
+    nametable_banks: [u8; 4],
+    /// PRG banks at $8000 ($E000-$E7FF) -SPP PPPP, $A000 ($E800-$EFFF) and $C000 ($F000-$F7FF)
+    prg_banks: [u8; 3],
+    /// PRG RAM write protect ($F800-$FFFF) KKKK DCBA, shared with the sound address port
+    write_protect: u8,
+
+    /// IRQ counter ($5000-$5FFF) EIII IIII IIII IIII, enable and 15-bit counter
+    irq_counter: u16,
+    irq_pending: bool,
+
+    audio: Namco163Audio,
+}
+
+impl Mapper19 {
+    pub fn new(rom: &mut RomFile) -> Self {
+        let chr_ram = !rom.header.has_chr_rom();
+        let chr_data = if chr_ram {
+            rom.chr_ram()
+        } else {
+            Box::from(rom.chr_data.as_ref())
+        };
+
+        Self {
+            header: rom.header,
+            pgr_data: Box::from(rom.pgr_data.as_ref()),
+            chr_data,
+            prg_ram: rom.prg_ram(0x2000),
+            chr_ram,
+            chr_banks: [0; 8],
+            nametable_banks: [0xE0, 0xE1, 0xE0, 0xE1],
+            prg_banks: [0; 3],
+            write_protect: 0,
+            irq_counter: 0,
+            irq_pending: false,
+            audio: Namco163Audio::new(),
+        }
+    }
+
+    fn sound_enabled(&self) -> bool {
+        (self.prg_banks[0] & 0x40) == 0
+    }
+
+    /// Writes need $4x in the upper nibble and the 2KB region unprotected
+    fn prg_ram_writable(&self, addr: u16) -> bool {
+        let region = (addr - 0x6000) / 0x800;
+        (self.write_protect & 0xF0) == 0x40 && (self.write_protect >> region) & 0x01 == 0
+    }
+
+    fn prg_address(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x8000..=0xDFFF => {
+                (self.prg_banks[((addr - 0x8000) / 0x2000) as usize] & 0x3F) as usize
+            }
+            _ => self.pgr_data.len() / 0x2000 - 1,
+        };
+
+        (bank * 0x2000 + (addr & 0x1FFF) as usize) % self.pgr_data.len()
+    }
+
+    fn chr_address(&self, bank: u8, addr: u16) -> usize {
+        (bank as usize * 0x400 + (addr & 0x03FF) as usize) % self.chr_data.len()
+    }
+
+    /// CHR ROM bank backing a nametable, `None` for CIRAM
+    fn nametable_rom_bank(&self, addr: u16) -> Option<u8> {
+        let bank = self.nametable_banks[((addr - 0x2000) / 0x400 % 4) as usize];
+        if bank < 0xE0 {
+            Some(bank)
+        } else {
+            None
+        }
+    }
+
+    fn read_register(&self, addr: u16) -> u8 {
+        match addr {
+            0x4800..=0x4FFF => self.audio.read_data(),
+            0x5000..=0x57FF => self.irq_counter as u8,
+            0x5800..=0x5FFF => (self.irq_counter >> 8) as u8,
+            _ => 0,
+        }
+    }
+}
+
+impl Mapper for Mapper19 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x5FFF => self.read_register(addr),
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()],
+            0x8000..=0xFFFF => self.pgr_data[self.prg_address(addr)],
+        }
+    }
+
+    fn peek_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x4800..=0x4FFF => self.audio.peek_data(),
+            _ => self.read_prg(addr),
+        }
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/Namco_163#Registers
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4800..=0x4FFF => self.audio.write_data(value),
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0xFF00) | value as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | (value as u16) << 8;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF if self.prg_ram_writable(addr) => {
+                self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()] = value;
+            }
+            0x8000..=0xBFFF => self.chr_banks[((addr - 0x8000) / 0x800) as usize] = value,
+            0xC000..=0xDFFF => {
+                self.nametable_banks[((addr - 0xC000) / 0x800) as usize] = value;
+            }
+            0xE000..=0xF7FF => self.prg_banks[((addr - 0xE000) / 0x800) as usize] = value,
+            0xF800..=0xFFFF => {
+                self.write_protect = value;
+                self.audio.write_address(value);
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        let bank = self.chr_banks[(addr / 0x400) as usize];
+        self.chr_data[self.chr_address(bank, addr)]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if self.chr_ram {
+            let bank = self.chr_banks[(addr / 0x400) as usize];
+            let address = self.chr_address(bank, addr);
+            self.chr_data[address] = value;
+        }
+    }
+
+    /// The counter counts up on every CPU cycle while enabled, stopping at $7FFF with an IRQ
+    fn clock(&mut self) {
+        if (self.irq_counter & 0x8000) != 0 && (self.irq_counter & 0x7FFF) != 0x7FFF {
+            self.irq_counter += 1;
+            if (self.irq_counter & 0x7FFF) == 0x7FFF {
+                self.irq_pending = true;
+            }
+        }
+
+        if self.sound_enabled() {
+            self.audio.clock();
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    /// CIRAM pages picked by the nametable banks, the CHR ROM ones are served by
+    /// `read_nametable`
+    fn mirroring(&self) -> Option<Mirroring> {
+        let mut pages = [0u8; 4];
+        for (page, bank) in pages.iter_mut().zip(self.nametable_banks.iter()) {
+            *page = bank & 0x01;
+        }
+        Some(Mirroring::Custom(pages))
+    }
+
+    fn read_nametable(&self, addr: u16) -> Option<u8> {
+        self.nametable_rom_bank(addr)
+            .map(|bank| self.chr_data[self.chr_address(bank, addr)])
+    }
+
+    /// Nametables in CHR ROM can't be written
+    fn write_nametable(&mut self, addr: u16, _value: u8) -> bool {
+        self.nametable_rom_bank(addr).is_some()
+    }
+
+    fn audio_output(&self) -> f32 {
+        if self.sound_enabled() {
+            self.audio.output()
+        } else {
+            0.0
+        }
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    /// The battery keeps both the PRG RAM and the sound RAM
+    fn save_data(&self) -> Option<Vec<u8>> {
+        let mut data = self.prg_ram.to_vec();
+        data.extend_from_slice(&self.audio.ram);
+        Some(data)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let prg_ram_size = self.prg_ram.len().min(data.len());
+        self.prg_ram[..prg_ram_size].copy_from_slice(&data[..prg_ram_size]);
+
+        let sound_ram = &data[prg_ram_size..];
+        let sound_ram_size = self.audio.ram.len().min(sound_ram.len());
+        self.audio.ram[..sound_ram_size].copy_from_slice(&sound_ram[..sound_ram_size]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper19;
+    use crate::rom::mapper::Mapper;
+    use crate::rom::rom_file::RomFile;
+    use crate::rom::rom_header::RomHeader;
+
+    fn namco163() -> Mapper19 {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = 16;
+        header[5] = 16;
+        header[6] = 0x32; // Mapper 19 with a battery
+        let pgr_data: Vec<u8> = (0..0x40000).map(|i| (i / 0x2000) as u8).collect();
+        let chr_data: Vec<u8> = (0..0x20000).map(|i| (i / 0x400) as u8).collect();
+        let mut rom = RomFile {
+            header: RomHeader::new(&header),
+            pgr_data: pgr_data.into_boxed_slice(),
+            chr_data: chr_data.into_boxed_slice(),
+            trainer: None,
+        };
+        Mapper19::new(&mut rom)
+    }
+
+    #[test]
+    fn banking_and_nametables() {
+        let mut mapper = namco163();
+        mapper.write_prg(0xE000, 3);
+        mapper.write_prg(0xF000, 5);
+        assert_eq!(mapper.read_prg(0x8000), 3);
+        assert_eq!(mapper.read_prg(0xC000), 5);
+        assert_eq!(mapper.read_prg(0xE000), 31);
+
+        mapper.write_prg(0xB800, 0x21);
+        assert_eq!(mapper.read_chr(0x1C00), 0x21);
+
+        // $2400 from CHR ROM, $2000 from CIRAM
+        mapper.write_prg(0xC800, 0x12);
+        assert_eq!(mapper.read_nametable(0x2400), Some(0x12));
+        assert_eq!(mapper.read_nametable(0x2000), None);
+    }
+
+    #[test]
+    fn irq_counter() {
+        let mut mapper = namco163();
+        mapper.write_prg(0x5000, 0xFD);
+        mapper.write_prg(0x5800, 0xFF);
+        assert_eq!(mapper.read_prg(0x5800), 0xFF);
+
+        mapper.clock();
+        assert!(!mapper.irq_pending());
+        mapper.clock();
+        assert!(mapper.irq_pending());
+        mapper.clock();
+        assert_eq!(mapper.read_prg(0x5000), 0xFF);
+
+        mapper.write_prg(0x5800, 0x00); // Acknowledge
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn sound_ram_is_saved() {
+        let mut mapper = namco163();
+        mapper.write_prg(0xF800, 0x40); // PRG RAM writes enabled, sound RAM address $40
+        mapper.write_prg(0x4800, 0xAB);
+        mapper.write_prg(0x6000, 0xCD);
+
+        let data = mapper.save_data().unwrap();
+        assert_eq!(data.len(), 0x2000 + 0x80);
+        assert_eq!(data[0], 0xCD);
+        assert_eq!(data[0x2040], 0xAB);
+
+        let mut restored = namco163();
+        restored.load_save_data(&data);
+        restored.write_prg(0xF800, 0x40);
+        assert_eq!(restored.read_prg(0x4800), 0xAB);
+    }
+}
@@ -0,0 +1,109 @@
+use crate::rom::mapper::bus_conflict;
+use crate::rom::mapper::Mapper;
+use crate::rom::rom_file::RomError;
+use crate::rom::rom_file::RomFile;
+use crate::rom::rom_header::RomHeader;
+
+/// The generic designation UxROM refers to the Nintendo cartridge boards NES-UNROM, NES-UOROM,
+/// HVC-UN1ROM their HVC counterparts, and clone boards. The iNES format assigns mapper 2 to UxROM.
+/// https://wiki.nesdev.com/w/index.php/UxROM
+pub struct Mapper2 {
+    pub header: RomHeader,
+    pub pgr_data: Box<[u8]>,
+    pub chr_data: Box<[u8]>,
+    /// Selects the 16KB PRG ROM bank at $8000-$BFFF
+    prg_bank: u8,
+}
+
+impl Mapper2 {
+    pub fn new(rom: &mut RomFile) -> Result<Self, RomError> {
+        // The last 16KB bank is fixed at $C000
+        if rom.pgr_data.len() < 0x4000 {
+            return Err(RomError::InconsistentSizes {
+                prg_rom: rom.pgr_data.len(),
+                chr_rom: rom.chr_data.len(),
+            });
+        }
+
+        let chr_data = if !rom.header.has_chr_rom() {
+            rom.chr_ram()
+        } else {
+            Box::from(rom.chr_data.as_ref())
+        };
+
+        Ok(Self {
+            header: rom.header,
+            pgr_data: Box::from(rom.pgr_data.as_ref()),
+            chr_data,
+            prg_bank: 0,
+        })
+    }
+}
+
+impl Mapper for Mapper2 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        let offset = (addr & 0x3FFF) as usize;
+        match addr {
+            0x0000..=0x7FFF => 0,
+            // CPU $8000-$BFFF: 16 KB switchable PRG ROM bank
+            0x8000..=0xBFFF => {
+                self.pgr_data[(self.prg_bank as usize * 0x4000 + offset) % self.pgr_data.len()]
+            }
+            // CPU $C000-$FFFF: 16 KB PRG ROM bank, fixed to the last bank
+            0xC000..=0xFFFF => self.pgr_data[self.pgr_data.len() - 0x4000 + offset],
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        if addr >= 0x8000 {
+            self.prg_bank = bus_conflict(self, addr, value);
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr_data[addr as usize % self.chr_data.len()]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if !self.header.has_chr_rom() {
+            self.chr_data[addr as usize] = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper2;
+    use crate::rom::mapper::Mapper;
+    use crate::rom::rom_file::RomFile;
+    use crate::rom::rom_header::RomHeader;
+
+    #[test]
+    fn bus_conflicts() {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = 8; // 128KB PRG ROM
+        header[6] = 0x20;
+        let mut pgr_data = vec![0xFFu8; 8 * 0x4000];
+        for bank in 0..8 {
+            pgr_data[bank * 0x4000] = bank as u8;
+        }
+        pgr_data[0x1C000 + 0x0100] = 0x05; // $C100 in the fixed bank
+        let mut rom = RomFile {
+            header: RomHeader::new(&header),
+            pgr_data: pgr_data.into_boxed_slice(),
+            chr_data: Box::new([]),
+            trainer: None,
+        };
+        let mut mapper = Mapper2::new(&mut rom).unwrap();
+
+        assert_eq!(mapper.read_prg(0xC000), 7);
+
+        mapper.write_prg(0xC001, 3);
+        assert_eq!(mapper.read_prg(0x8000), 3);
+
+        // $C100 holds $05, so writing $06 there selects bank 4
+        mapper.write_prg(0xC100, 6);
+        assert_eq!(mapper.read_prg(0x8000), 4);
+    }
+}
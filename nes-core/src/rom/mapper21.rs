@@ -0,0 +1,357 @@
+use crate::rom::mapper::Mapper;
+use crate::rom::mapper::Mirroring;
+use crate::rom::rom_file::RomError;
+use crate::rom::rom_file::RomFile;
+use crate::rom::rom_header::RomHeader;
+use crate::rom::vrc_irq::VrcIrq;
+
+/// The Konami VRC2 and VRC4 are ASIC mappers with 8KB PRG banking and 1KB CHR banking, the VRC4
+/// adds a PRG swap mode, one-screen mirroring and an IRQ counter. The boards wire different CPU
+/// address lines to the chip's A0 and A1 inputs, which the iNES format splits across mappers
+/// 21, 22, 23 and 25. NES 2.0 submappers pick a single wiring, otherwise both wirings of the
+/// mapper are decoded at once, as no game writes to the addresses that would collide. Without a
+/// submapper, VRC2 boards are told apart from VRC4 ones by their ROM and RAM sizes.
+/// https://wiki.nesdev.com/w/index.php/VRC2_and_VRC4
+pub struct Mapper21 {
+    pub header: RomHeader,
+    pub pgr_data: Box<[u8]>,
+    pub chr_data: Box<[u8]>,
+    pub prg_ram: Box<[u8]>,
+    chr_ram: bool,
+
+    /// CPU address lines connected to the chip's A0 and A1
+    a0_lines: u16,
+    a1_lines: u16,
+    /// VRC2 boards have no IRQ, PRG swap mode or one-screen mirroring
+    vrc2: bool,
+    /// VRC2a (mapper 22) ignores the lowest bit of the CHR banks
+    chr_shift: u8,
+
+    /// PRG select 0 ($8000-$8003) and 1 ($A000-$A003) ---P PPPP
+    prg_banks: [u8; 2],
+    /// Mirroring control ($9000-$9001) ---- --MM
+    mirroring: u8,
+    /// PRG swap mode and WRAM control ($9002-$9003) ---- --MW
+    prg_mode: u8,
+    /// CHR selects ($B000-$E003), written one nibble at a time
+    chr_banks: [u16; 8],
+    /// 1-bit latch read back at $6000-$6FFF by VRC2 games without PRG RAM
+    latch: u8,
+
+    irq: VrcIrq,
+}
+
+impl Mapper21 {
+    pub fn new(rom: &mut RomFile) -> Result<Self, RomError> {
+        // The last two 8KB banks are fixed
+        if rom.pgr_data.len() < 0x4000 {
+            return Err(RomError::InconsistentSizes {
+                prg_rom: rom.pgr_data.len(),
+                chr_rom: rom.chr_data.len(),
+            });
+        }
+
+        let chr_ram = !rom.header.has_chr_rom();
+        let chr_data = if chr_ram {
+            rom.chr_ram()
+        } else {
+            Box::from(rom.chr_data.as_ref())
+        };
+
+        // https://wiki.nesdev.com/w/index.php/VRC2_and_VRC4#Variants
+        let vrc2_sizes = is_vrc2_sized(rom);
+        let (a0_lines, a1_lines, vrc2) =
+            match (rom.header.get_mapper_id(), rom.header.get_submapper_id()) {
+                (21, 1) => (0x02, 0x04, false), // VRC4a
+                (21, 2) => (0x40, 0x80, false), // VRC4c
+                (21, _) => (0x02 | 0x40, 0x04 | 0x80, false),
+                (22, _) => (0x02, 0x01, true),  // VRC2a
+                (23, 1) => (0x01, 0x02, false), // VRC4f
+                (23, 2) => (0x04, 0x08, false), // VRC4e
+                (23, 3) => (0x01, 0x02, true),  // VRC2b
+                (23, 0) if vrc2_sizes => (0x01, 0x02, true),
+                (23, _) => (0x01 | 0x04, 0x02 | 0x08, false),
+                (25, 1) => (0x02, 0x01, false), // VRC4b
+                (25, 2) => (0x08, 0x04, false), // VRC4d
+                (25, 3) => (0x02, 0x01, true),  // VRC2c
+                (25, 0) if vrc2_sizes => (0x02, 0x01, true),
+                (_, _) => (0x02 | 0x08, 0x01 | 0x04, false),
+            };
+        let chr_shift = if rom.header.get_mapper_id() == 22 {
+            1
+        } else {
+            0
+        };
+
+        Ok(Self {
+            header: rom.header,
+            pgr_data: Box::from(rom.pgr_data.as_ref()),
+            chr_data,
+            prg_ram: rom.prg_ram(0x2000),
+            chr_ram,
+            a0_lines,
+            a1_lines,
+            vrc2,
+            chr_shift,
+            prg_banks: [0, 0],
+            mirroring: 0,
+            prg_mode: 0,
+            chr_banks: [0; 8],
+            latch: 0,
+            irq: VrcIrq::new(),
+        })
+    }
+
+    /// Register address with the board's wiring undone, $x000-$x003
+    fn register_address(&self, addr: u16) -> u16 {
+        let a0 = (addr & self.a0_lines) != 0;
+        let a1 = (addr & self.a1_lines) != 0;
+        (addr & 0xF000) | ((a1 as u16) << 1) | a0 as u16
+    }
+
+    /// VRC2 boards without a battery usually have no PRG RAM, only the 1-bit latch
+    fn has_prg_ram(&self) -> bool {
+        !self.vrc2 || (self.header.flags6 & 0x02) != 0
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.vrc2 || (self.prg_mode & 0x01) != 0
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/VRC2_and_VRC4#PRG_Swap_Mode_control_.28.249002.29
+    fn prg_address(&self, addr: u16) -> usize {
+        let second_last = self.pgr_data.len() / 0x2000 - 2;
+        let swap_mode = !self.vrc2 && (self.prg_mode & 0x02) != 0;
+
+        let bank = match (addr, swap_mode) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => self.prg_banks[0] as usize,
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => second_last,
+            (0xA000..=0xBFFF, _) => self.prg_banks[1] as usize,
+            _ => second_last + 1,
+        };
+
+        (bank * 0x2000 + (addr & 0x1FFF) as usize) % self.pgr_data.len()
+    }
+
+    fn chr_address(&self, addr: u16) -> usize {
+        let bank = (self.chr_banks[(addr / 0x400) as usize] >> self.chr_shift) as usize;
+        (bank * 0x400 + (addr & 0x03FF) as usize) % self.chr_data.len()
+    }
+
+    /// Each CHR select is split in a low ($x000/$x002) and a high ($x001/$x003) nibble
+    fn write_chr_select(&mut self, addr: u16, value: u8) {
+        let index = ((addr - 0xB000) / 0x1000 * 2 + (addr & 0x02) / 2) as usize;
+        let bank = self.chr_banks[index];
+
+        self.chr_banks[index] = if (addr & 0x01) == 0 {
+            (bank & 0x1F0) | (value & 0x0F) as u16
+        } else {
+            // VRC2 only has 4 bits on the high nibble
+            let high_mask = if self.vrc2 { 0x0F } else { 0x1F };
+            (bank & 0x00F) | (((value & high_mask) as u16) << 4)
+        };
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/VRC2_and_VRC4#Registers
+    fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x8000..=0x8003 => self.prg_banks[0] = value & 0x1F,
+            0x9000..=0x9001 if self.vrc2 => self.mirroring = value & 0x01,
+            0x9000..=0x9003 if self.vrc2 => {}
+            0x9000..=0x9001 => self.mirroring = value & 0x03,
+            0x9002..=0x9003 => self.prg_mode = value,
+            0xA000..=0xA003 => self.prg_banks[1] = value & 0x1F,
+            0xB000..=0xE003 => self.write_chr_select(addr, value),
+            0xF000..=0xF003 if self.vrc2 => {}
+            0xF000 => self.irq.write_latch_low(value),
+            0xF001 => self.irq.write_latch_high(value),
+            0xF002 => self.irq.write_control(value),
+            0xF003 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Mapper21 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x5FFF => 0,
+            0x6000..=0x6FFF if !self.has_prg_ram() => self.latch,
+            0x6000..=0x7FFF => {
+                if self.has_prg_ram() && self.prg_ram_enabled() {
+                    self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()]
+                } else {
+                    0
+                }
+            }
+            0x8000..=0xFFFF => self.pgr_data[self.prg_address(addr)],
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x5FFF => {}
+            0x6000..=0x6FFF if !self.has_prg_ram() => self.latch = value & 0x01,
+            0x6000..=0x7FFF => {
+                if self.has_prg_ram() && self.prg_ram_enabled() {
+                    self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()] = value;
+                }
+            }
+            0x8000..=0xFFFF => self.write_register(self.register_address(addr), value),
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr_data[self.chr_address(addr)]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if self.chr_ram {
+            let address = self.chr_address(addr);
+            self.chr_data[address] = value;
+        }
+    }
+
+    fn clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        match self.mirroring {
+            0 => Some(Mirroring::Vertical),
+            1 => Some(Mirroring::Horizontal),
+            2 => Some(Mirroring::SingleScreenLower),
+            _ => Some(Mirroring::SingleScreenUpper),
+        }
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+/// VRC2 games like Contra (J) and Ganbare Goemon Gaiden have up to 128KB of PRG and CHR ROM and
+/// no PRG RAM, while VRC4 games mostly have either more ROM or PRG RAM.
+fn is_vrc2_sized(rom: &RomFile) -> bool {
+    let prg_ram = if rom.header.is_nes2() {
+        rom.header.prg_ram_bytes() + rom.header.prg_nvram_bytes() != 0
+    } else {
+        rom.header.has_battery()
+    };
+
+    !prg_ram && rom.pgr_data.len() <= 0x20000 && rom.chr_data.len() <= 0x20000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper21;
+    use crate::rom::mapper::Mapper;
+    use crate::rom::rom_file::RomFile;
+    use crate::rom::rom_header::RomHeader;
+
+    fn vrc(mapper_id: u8, submapper_id: u8) -> Mapper21 {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = 16;
+        header[5] = 32;
+        header[6] = (mapper_id & 0x0F) << 4;
+        header[7] = (mapper_id & 0xF0) | 0x08; // NES 2.0
+        header[8] = submapper_id << 4;
+        let pgr_data: Vec<u8> = (0..0x40000).map(|i| (i / 0x2000) as u8).collect();
+        let chr_data: Vec<u8> = (0..0x40000).map(|i| (i / 0x400) as u8).collect();
+        let mut rom = RomFile {
+            header: RomHeader::new(&header),
+            pgr_data: pgr_data.into_boxed_slice(),
+            chr_data: chr_data.into_boxed_slice(),
+            trainer: None,
+        };
+        Mapper21::new(&mut rom).unwrap()
+    }
+
+    /// iNES dump with 128KB of PRG and CHR ROM, like the VRC2 games
+    fn ines_vrc(mapper_id: u8, battery: bool) -> Mapper21 {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = 8;
+        header[5] = 16;
+        header[6] = (mapper_id & 0x0F) << 4 | if battery { 0x02 } else { 0x00 };
+        header[7] = mapper_id & 0xF0;
+        let mut rom = RomFile {
+            header: RomHeader::new(&header),
+            pgr_data: (0..0x20000).map(|i| (i / 0x2000) as u8).collect(),
+            chr_data: (0..0x20000).map(|i| (i / 0x400) as u8).collect(),
+            trainer: None,
+        };
+        Mapper21::new(&mut rom).unwrap()
+    }
+
+    #[test]
+    fn address_wirings() {
+        // CHR select 3 is the high half of $C000, at A1 (low nibble) and A0+A1 (high nibble)
+        let wirings = [
+            (21, 1, 0xC004, 0xC006), // VRC4a: A1, A2
+            (21, 2, 0xC080, 0xC0C0), // VRC4c: A6, A7
+            (21, 0, 0xC080, 0xC006),
+            (23, 2, 0xC008, 0xC00C), // VRC4e: A2, A3
+            (25, 2, 0xC004, 0xC00C), // VRC4d: A3, A2
+            (25, 0, 0xC001, 0xC003),
+        ];
+
+        for &(mapper_id, submapper_id, low, high) in wirings.iter() {
+            let mut mapper = vrc(mapper_id, submapper_id);
+            mapper.write_prg(low, 0x05);
+            mapper.write_prg(high, 0x03);
+            assert_eq!(mapper.read_chr(0x0C00), 0x35, "mapper {}", mapper_id);
+        }
+
+        // VRC2a drops the lowest bit of the bank
+        let mut mapper = vrc(22, 0);
+        mapper.write_prg(0xB000, 0x05);
+        assert_eq!(mapper.read_chr(0x0000), 0x02);
+    }
+
+    #[test]
+    fn prg_swap_mode() {
+        let mut mapper = vrc(23, 1);
+        mapper.write_prg(0x8000, 4);
+        mapper.write_prg(0xA000, 5);
+        assert_eq!(mapper.read_prg(0x8000), 4);
+        assert_eq!(mapper.read_prg(0xA000), 5);
+        assert_eq!(mapper.read_prg(0xC000), 30);
+        assert_eq!(mapper.read_prg(0xE000), 31);
+
+        mapper.write_prg(0x9002, 0x02);
+        assert_eq!(mapper.read_prg(0x8000), 30);
+        assert_eq!(mapper.read_prg(0xC000), 4);
+
+        // VRC2b has no swap mode
+        let mut mapper = vrc(23, 3);
+        mapper.write_prg(0x8000, 4);
+        mapper.write_prg(0x9002, 0x02);
+        assert_eq!(mapper.read_prg(0x8000), 4);
+    }
+
+    #[test]
+    fn vrc2_from_sizes() {
+        for &mapper_id in &[23, 25] {
+            let mut mapper = ines_vrc(mapper_id, false);
+            assert!(mapper.vrc2, "mapper {}", mapper_id);
+            mapper.write_prg(0x8000, 4);
+            mapper.write_prg(0x9002, 0x02);
+            assert_eq!(mapper.read_prg(0x8000), 4);
+
+            // PRG RAM or more ROM than a VRC2 board has means VRC4
+            assert!(!ines_vrc(mapper_id, true).vrc2, "mapper {}", mapper_id);
+            assert!(!vrc(mapper_id, 0).vrc2, "mapper {}", mapper_id);
+        }
+        assert!(!ines_vrc(21, false).vrc2);
+    }
+}
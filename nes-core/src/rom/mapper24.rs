@@ -0,0 +1,249 @@
+use crate::apu::vrc6::Vrc6Audio;
+use crate::rom::mapper::Mapper;
+use crate::rom::mapper::Mirroring;
+use crate::rom::rom_file::RomFile;
+use crate::rom::rom_header::RomHeader;
+use crate::rom::vrc_irq::VrcIrq;
+
+/// The Konami VRC6 is an ASIC mapper with PRG and CHR banking, a CPU cycle IRQ counter and
+/// three extra sound channels. The iNES format assigns mapper 24 to VRC6a and mapper 26 to
+/// VRC6b, which swaps the A0 and A1 register address lines.
+/// Nametables sourced from CHR ROM ($B003 bit 4) are not emulated.
+/// https://wiki.nesdev.com/w/index.php/VRC6
+pub struct Mapper24 {
+    pub header: RomHeader,
+    pub pgr_data: Box<[u8]>,
+    pub chr_data: Box<[u8]>,
+    pub prg_ram: Box<[u8]>,
+    chr_ram: bool,
+    /// VRC6b (mapper 26)
+    swapped_lines: bool,
+
+    /// 16KB PRG bank at $8000 ($8000-$8003) ---- PPPP
+    prg_bank_16: u8,
+    /// 8KB PRG bank at $C000 ($C000-$C003) ---P PPPP
+    prg_bank_8: u8,
+    /// PPU banking style ($B003) W.PN MMDD
+    banking_style: u8,
+    /// R0-R7 CHR banks ($D000-$E003)
+    chr_banks: [u8; 8],
+
+    irq: VrcIrq,
+    audio: Vrc6Audio,
+}
+
+impl Mapper24 {
+    pub fn new(rom: &mut RomFile) -> Self {
+        let chr_ram = !rom.header.has_chr_rom();
+        let chr_data = if chr_ram {
+            rom.chr_ram()
+        } else {
+            Box::from(rom.chr_data.as_ref())
+        };
+
+        Self {
+            header: rom.header,
+            pgr_data: Box::from(rom.pgr_data.as_ref()),
+            chr_data,
+            prg_ram: rom.prg_ram(0x2000),
+            chr_ram,
+            swapped_lines: rom.header.get_mapper_id() == 26,
+            prg_bank_16: 0,
+            prg_bank_8: 0,
+            banking_style: 0,
+            chr_banks: [0; 8],
+            irq: VrcIrq::new(),
+            audio: Vrc6Audio::new(),
+        }
+    }
+
+    /// Register address as seen by a VRC6a
+    fn register_address(&self, addr: u16) -> u16 {
+        let addr = addr & 0xF003;
+        if self.swapped_lines {
+            (addr & 0xF000) | ((addr & 0x01) << 1) | ((addr & 0x02) >> 1)
+        } else {
+            addr
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        (self.banking_style & 0x80) != 0
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/VRC6#PRG_Banking
+    fn prg_address(&self, addr: u16) -> usize {
+        let address = match addr {
+            0x8000..=0xBFFF => self.prg_bank_16 as usize * 0x4000 + (addr & 0x3FFF) as usize,
+            0xC000..=0xDFFF => self.prg_bank_8 as usize * 0x2000 + (addr & 0x1FFF) as usize,
+            _ => self.pgr_data.len() - 0x2000 + (addr & 0x1FFF) as usize,
+        };
+
+        address % self.pgr_data.len()
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/VRC6#CHR_Banking
+    fn chr_address(&self, addr: u16) -> usize {
+        let slot = (addr / 0x400) as usize;
+        let a10 = (slot & 0x01) as u8;
+
+        // 2KB banks take A10 from the PPU address, unless bit 5 asks for the register's
+        let bank_2k = |register: u8| {
+            if (self.banking_style & 0x20) != 0 {
+                register
+            } else {
+                (register & 0xFE) | a10
+            }
+        };
+
+        let bank = match (self.banking_style & 0x03, slot) {
+            (0, _) => self.chr_banks[slot],
+            (1, _) => bank_2k(self.chr_banks[slot / 2]),
+            (_, 0..=3) => self.chr_banks[slot],
+            (_, _) => bank_2k(self.chr_banks[4 + (slot - 4) / 2]),
+        };
+
+        (bank as usize * 0x400 + (addr & 0x03FF) as usize) % self.chr_data.len()
+    }
+    /// https://wiki.nesdev.com/w/index.php/VRC6#Registers
+    fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x8000..=0x8003 => self.prg_bank_16 = value & 0x0F,
+            0x9000..=0xB002 => self.audio.write_register(addr, value),
+            0xB003 => self.banking_style = value,
+            0xC000..=0xC003 => self.prg_bank_8 = value & 0x1F,
+            0xD000..=0xD003 => self.chr_banks[(addr - 0xD000) as usize] = value,
+            0xE000..=0xE003 => self.chr_banks[4 + (addr - 0xE000) as usize] = value,
+            0xF000 => self.irq.write_latch(value),
+            0xF001 => self.irq.write_control(value),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Mapper24 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x5FFF => 0,
+            0x6000..=0x7FFF => {
+                if self.prg_ram_enabled() {
+                    self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()]
+                } else {
+                    0
+                }
+            }
+            0x8000..=0xFFFF => self.pgr_data[self.prg_address(addr)],
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x5FFF => {}
+            0x6000..=0x7FFF => {
+                if self.prg_ram_enabled() {
+                    self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()] = value;
+                }
+            }
+            0x8000..=0xFFFF => self.write_register(self.register_address(addr), value),
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr_data[self.chr_address(addr)]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if self.chr_ram {
+            let address = self.chr_address(addr);
+            self.chr_data[address] = value;
+        }
+    }
+
+    fn clock(&mut self) {
+        self.irq.clock();
+        self.audio.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending
+    }
+
+    /// Mirroring ($B003) xxxx MMxx
+    fn mirroring(&self) -> Option<Mirroring> {
+        match (self.banking_style >> 2) & 0x03 {
+            0 => Some(Mirroring::Vertical),
+            1 => Some(Mirroring::Horizontal),
+            2 => Some(Mirroring::SingleScreenLower),
+            _ => Some(Mirroring::SingleScreenUpper),
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper24;
+    use crate::rom::mapper::Mapper;
+    use crate::rom::rom_file::RomFile;
+    use crate::rom::rom_header::RomHeader;
+
+    fn vrc6(mapper_id: u8) -> Mapper24 {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = 16;
+        header[5] = 16;
+        header[6] = (mapper_id & 0x0F) << 4;
+        header[7] = mapper_id & 0xF0;
+        let pgr_data: Vec<u8> = (0..0x40000).map(|i| (i / 0x2000) as u8).collect();
+        let chr_data: Vec<u8> = (0..0x20000).map(|i| (i / 0x400) as u8).collect();
+        let mut rom = RomFile {
+            header: RomHeader::new(&header),
+            pgr_data: pgr_data.into_boxed_slice(),
+            chr_data: chr_data.into_boxed_slice(),
+            trainer: None,
+        };
+        Mapper24::new(&mut rom)
+    }
+
+    #[test]
+    fn banking() {
+        let mut mapper = vrc6(24);
+        mapper.write_prg(0x8000, 3);
+        mapper.write_prg(0xC000, 9);
+        assert_eq!(mapper.read_prg(0x8000), 6);
+        assert_eq!(mapper.read_prg(0xA000), 7);
+        assert_eq!(mapper.read_prg(0xC000), 9);
+        assert_eq!(mapper.read_prg(0xE000), 31);
+
+        mapper.write_prg(0xE002, 0x42); // R6
+        assert_eq!(mapper.read_chr(0x1800), 0x42);
+
+        // VRC6b swaps A0 and A1, R5 moves from $E001 to $E002
+        let mut mapper = vrc6(26);
+        mapper.write_prg(0xE002, 0x13);
+        assert_eq!(mapper.read_chr(0x1400), 0x13);
+    }
+
+    #[test]
+    fn prg_ram_enable() {
+        let mut mapper = vrc6(24);
+        mapper.write_prg(0x6000, 0x55);
+        assert_eq!(mapper.read_prg(0x6000), 0);
+
+        mapper.write_prg(0xB003, 0x80);
+        mapper.write_prg(0x6000, 0x55);
+        assert_eq!(mapper.read_prg(0x6000), 0x55);
+    }
+}
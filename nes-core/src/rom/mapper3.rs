@@ -0,0 +1,58 @@
+use crate::rom::mapper::bus_conflict;
+use crate::rom::mapper::Mapper;
+use crate::rom::rom_file::RomFile;
+use crate::rom::rom_header::RomHeader;
+
+/// The generic designation CNROM refers to the Nintendo cartridge boards NES-CNROM, HVC-CNROM
+/// and clone boards. The iNES format assigns mapper 3 to CNROM.
+/// https://wiki.nesdev.com/w/index.php/CNROM
+pub struct Mapper3 {
+    pub header: RomHeader,
+    pub pgr_data: Box<[u8]>,
+    pub chr_data: Box<[u8]>,
+    /// Selects the 8KB CHR ROM bank at PPU $0000-$1FFF
+    chr_bank: u8,
+}
+
+impl Mapper3 {
+    pub fn new(rom: &mut RomFile) -> Self {
+        let chr_data = if !rom.header.has_chr_rom() {
+            rom.chr_ram()
+        } else {
+            Box::from(rom.chr_data.as_ref())
+        };
+
+        Self {
+            header: rom.header,
+            pgr_data: Box::from(rom.pgr_data.as_ref()),
+            chr_data,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Mapper3 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x7FFF => 0,
+            // 16KB or 32KB PRG ROM, mirrored like NROM
+            0x8000..=0xFFFF => self.pgr_data[(addr - 0x8000) as usize % self.pgr_data.len()],
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        if addr >= 0x8000 {
+            self.chr_bank = bus_conflict(self, addr, value);
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr_data[(self.chr_bank as usize * 0x2000 + addr as usize) % self.chr_data.len()]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if !self.header.has_chr_rom() {
+            self.chr_data[addr as usize] = value;
+        }
+    }
+}
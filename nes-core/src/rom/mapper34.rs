@@ -0,0 +1,102 @@
+use crate::rom::mapper::bus_conflict;
+use crate::rom::mapper::Mapper;
+use crate::rom::rom_file::RomFile;
+use crate::rom::rom_header::RomHeader;
+
+/// The iNES format assigns mapper 34 to two different boards: BNROM, a discrete board with
+/// 32KB PRG ROM banking and CHR RAM, and NINA-001, with 32KB PRG ROM banking, two 4KB CHR ROM
+/// banks and 8KB of PRG RAM. Boards with CHR ROM are treated as NINA-001.
+/// https://wiki.nesdev.com/w/index.php/INES_Mapper_034
+pub struct Mapper34 {
+    pub header: RomHeader,
+    pub pgr_data: Box<[u8]>,
+    pub chr_data: Box<[u8]>,
+    pub prg_ram: Box<[u8]>,
+    nina001: bool,
+    prg_bank: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+}
+
+impl Mapper34 {
+    pub fn new(rom: &mut RomFile) -> Self {
+        let nina001 = rom.header.has_chr_rom();
+        let chr_data = if nina001 {
+            Box::from(rom.chr_data.as_ref())
+        } else {
+            rom.chr_ram()
+        };
+
+        Self {
+            header: rom.header,
+            pgr_data: Box::from(rom.pgr_data.as_ref()),
+            chr_data,
+            prg_ram: rom.prg_ram(0x2000),
+            nina001,
+            prg_bank: 0,
+            chr_bank_0: 0,
+            chr_bank_1: 1,
+        }
+    }
+}
+
+impl Mapper for Mapper34 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x5FFF => 0,
+            0x6000..=0x7FFF if self.nina001 => {
+                self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()]
+            }
+            0x6000..=0x7FFF => 0,
+            // CPU $8000-$FFFF: 32 KB switchable PRG ROM bank
+            0x8000..=0xFFFF => {
+                let bank = self.prg_bank as usize;
+                self.pgr_data[(bank * 0x8000 + (addr - 0x8000) as usize) % self.pgr_data.len()]
+            }
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.nina001 => {
+                // The registers are written through to the PRG RAM underneath them
+                self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()] = value;
+                match addr {
+                    0x7FFD => self.prg_bank = value & 0x01,
+                    0x7FFE => self.chr_bank_0 = value & 0x0F,
+                    0x7FFF => self.chr_bank_1 = value & 0x0F,
+                    _ => {}
+                }
+            }
+            0x8000..=0xFFFF if !self.nina001 => self.prg_bank = bus_conflict(self, addr, value),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        if !self.nina001 {
+            return self.chr_data[addr as usize];
+        }
+
+        let bank = if addr < 0x1000 {
+            self.chr_bank_0
+        } else {
+            self.chr_bank_1
+        } as usize;
+        self.chr_data[(bank * 0x1000 + (addr & 0x0FFF) as usize) % self.chr_data.len()]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if !self.nina001 {
+            self.chr_data[addr as usize] = value;
+        }
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+}
@@ -0,0 +1,310 @@
+use crate::rom::mapper::Mapper;
+use crate::rom::mapper::Mirroring;
+use crate::rom::rom_file::RomError;
+use crate::rom::rom_file::RomFile;
+use crate::rom::rom_header::RomHeader;
+
+/// Minimum number of M2 cycles (CPU cycles) A12 has to stay low for its next rise to clock the
+/// scanline counter, a bit over 3 like the real filter. This keeps the background tile fetches
+/// (A12 low for 4 dots) and the end of the scanline (9 dots from the unused nametable fetches to
+/// the first pattern fetch) from clocking it when the background uses $1000.
+const A12_LOW_CYCLES: u8 = 4;
+
+/// The Nintendo MMC3 is a mapper ASIC used in Nintendo's TxROM Game Pak boards.
+/// The iNES format assigns mapper 4 to TxROM.
+/// https://wiki.nesdev.com/w/index.php/MMC3
+pub struct Mapper4 {
+    pub header: RomHeader,
+    pub pgr_data: Box<[u8]>,
+    pub chr_data: Box<[u8]>,
+    pub prg_ram: Box<[u8]>,
+    chr_ram: bool,
+
+    /// Bank select ($8000-$9FFE, even) CPMx xRRR
+    bank_select: u8,
+    /// R0-R7 bank data ($8001-$9FFF, odd)
+    registers: [u8; 8],
+    /// Mirroring ($A000-$BFFE, even) (0: vertical; 1: horizontal)
+    mirroring: u8,
+    /// PRG RAM protect ($A001-$BFFF, odd) RWXX xxxx
+    prg_ram_protect: u8,
+
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    /// Level of PPU A12 on the last PPU bus access
+    a12_high: bool,
+    /// M2 cycles since A12 went low
+    a12_low_cycles: u8,
+}
+
+impl Mapper4 {
+    pub fn new(rom: &mut RomFile) -> Result<Self, RomError> {
+        // The last two 8KB banks are fixed
+        if rom.pgr_data.len() < 0x4000 {
+            return Err(RomError::InconsistentSizes {
+                prg_rom: rom.pgr_data.len(),
+                chr_rom: rom.chr_data.len(),
+            });
+        }
+
+        let chr_ram = !rom.header.has_chr_rom();
+        let chr_data = if chr_ram {
+            rom.chr_ram()
+        } else {
+            Box::from(rom.chr_data.as_ref())
+        };
+
+        Ok(Self {
+            header: rom.header,
+            pgr_data: Box::from(rom.pgr_data.as_ref()),
+            chr_data,
+            prg_ram: rom.prg_ram(0x2000),
+            chr_ram,
+            bank_select: 0,
+            registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring: 0,
+            prg_ram_protect: 0x80,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12_high: false,
+            a12_low_cycles: 0,
+        })
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/MMC3#PRG_Banks
+    fn prg_address(&self, addr: u16) -> usize {
+        let second_last = self.pgr_data.len() / 0x2000 - 2;
+        let prg_mode = (self.bank_select & 0x40) != 0;
+
+        let bank = match (addr, prg_mode) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => self.registers[6] as usize,
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => second_last,
+            (0xA000..=0xBFFF, _) => self.registers[7] as usize,
+            _ => second_last + 1,
+        };
+
+        (bank * 0x2000 + (addr & 0x1FFF) as usize) % self.pgr_data.len()
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/MMC3#CHR_Banks
+    fn chr_address(&self, addr: u16) -> usize {
+        // CHR A12 inversion swaps the 2KB and 1KB halves of the pattern tables
+        let addr = if (self.bank_select & 0x80) != 0 {
+            addr ^ 0x1000
+        } else {
+            addr
+        };
+
+        let address = match addr {
+            0x0000..=0x07FF => (self.registers[0] as usize & 0xFE) * 0x400 + addr as usize,
+            0x0800..=0x0FFF => {
+                (self.registers[1] as usize & 0xFE) * 0x400 + (addr - 0x0800) as usize
+            }
+            _ => {
+                let register = 2 + ((addr - 0x1000) / 0x400) as usize;
+                self.registers[register] as usize * 0x400 + (addr & 0x03FF) as usize
+            }
+        };
+
+        address % self.chr_data.len()
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/MMC3#IRQ_Specifics
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Mapper4 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x5FFF => 0,
+            0x6000..=0x7FFF => {
+                if (self.prg_ram_protect & 0x80) != 0 {
+                    self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()]
+                } else {
+                    0
+                }
+            }
+            0x8000..=0xFFFF => self.pgr_data[self.prg_address(addr)],
+        }
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/MMC3#Registers
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        let even = (addr & 0x01) == 0;
+
+        match addr {
+            0x0000..=0x5FFF => {}
+            0x6000..=0x7FFF => {
+                // Writes need the chip enabled and write protection off
+                if (self.prg_ram_protect & 0xC0) == 0x80 {
+                    self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()] = value;
+                }
+            }
+            0x8000..=0x9FFF if even => self.bank_select = value,
+            0x8000..=0x9FFF => self.registers[(self.bank_select & 0x07) as usize] = value,
+            0xA000..=0xBFFF if even => self.mirroring = value & 0x01,
+            0xA000..=0xBFFF => self.prg_ram_protect = value,
+            0xC000..=0xDFFF if even => self.irq_latch = value,
+            0xC000..=0xDFFF => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xE000..=0xFFFF if even => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            0xE000..=0xFFFF => self.irq_enabled = true,
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr_data[self.chr_address(addr)]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if self.chr_ram {
+            let address = self.chr_address(addr);
+            self.chr_data[address] = value;
+        }
+    }
+
+    /// The scanline counter is clocked by rises of PPU A12, which happen once per scanline
+    /// when the background and sprites use different pattern tables
+    fn ppu_address(&mut self, addr: u16) {
+        let a12_high = (addr & 0x1000) != 0;
+        if a12_high {
+            if !self.a12_high && self.a12_low_cycles >= A12_LOW_CYCLES {
+                self.clock_irq_counter();
+            }
+            self.a12_low_cycles = 0;
+        }
+        self.a12_high = a12_high;
+    }
+
+    fn clock(&mut self) {
+        if !self.a12_high {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    /// Boards wired for four-screen VRAM ignore the mirroring register
+    fn mirroring(&self) -> Option<Mirroring> {
+        match self.header.get_mirroring() {
+            Mirroring::FourScreen => None,
+            _ if self.mirroring == 0 => Some(Mirroring::Vertical),
+            _ => Some(Mirroring::Horizontal),
+        }
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper4;
+    use crate::rom::mapper::Mapper;
+    use crate::rom::rom_file::RomFile;
+    use crate::rom::rom_header::RomHeader;
+
+    /// PPU reads of a rendered scanline, one every 2 dots with a CPU cycle every 3 dots
+    fn scanline(mapper: &mut Mapper4, background: u16, sprites: u16) {
+        let mut fetches = Vec::new();
+        for _ in 0..32 {
+            fetches.extend_from_slice(&[0x2000, 0x23C0, background, background + 8]);
+        }
+        for _ in 0..8 {
+            fetches.extend_from_slice(&[0x2000, 0x2000, sprites | 0x0FF0, sprites | 0x0FF8]);
+        }
+        for _ in 0..2 {
+            fetches.extend_from_slice(&[0x2000, 0x23C0, background, background + 8]);
+        }
+        fetches.extend_from_slice(&[0x2000, 0x2000]);
+
+        for dot in 0..341 {
+            if (dot & 0x01) != 0 && dot / 2 < fetches.len() {
+                mapper.ppu_address(fetches[dot / 2]);
+            }
+            if dot % 3 == 2 {
+                mapper.clock();
+            }
+        }
+    }
+
+    fn mmc3(latch: u8) -> Mapper4 {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = 2;
+        header[5] = 1;
+        header[6] = 0x40;
+        let mut rom = RomFile {
+            header: RomHeader::new(&header),
+            pgr_data: vec![0u8; 0x8000].into_boxed_slice(),
+            chr_data: vec![0u8; 0x2000].into_boxed_slice(),
+            trainer: None,
+        };
+        let mut mapper = Mapper4::new(&mut rom).unwrap();
+
+        mapper.write_prg(0xC000, latch);
+        mapper.write_prg(0xC001, 0); // Reload
+        mapper.write_prg(0xE001, 0); // Enable
+        mapper
+    }
+
+    #[test]
+    fn scanline_irq() {
+        // Background tiles from $0000 with sprites from $1000
+        let mut mapper = mmc3(3);
+
+        // Reload, then 3, 2, 1 down to 0
+        for _ in 0..3 {
+            scanline(&mut mapper, 0x0000, 0x1000);
+            assert!(!mapper.irq_pending());
+        }
+        scanline(&mut mapper, 0x0000, 0x1000);
+        assert!(mapper.irq_pending());
+
+        mapper.write_prg(0xE000, 0); // Acknowledge
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn scanline_irq_with_background_at_1000() {
+        // A12 rises on every background tile, but only stays low long enough during the sprite
+        // fetches
+        let mut mapper = mmc3(10);
+
+        for _ in 0..10 {
+            scanline(&mut mapper, 0x1000, 0x0000);
+            assert!(!mapper.irq_pending());
+        }
+        scanline(&mut mapper, 0x1000, 0x0000);
+        assert!(mapper.irq_pending());
+    }
+}
@@ -0,0 +1,608 @@
+use crate::apu::mmc5::Mmc5Audio;
+use crate::rom::mapper::Mapper;
+use crate::rom::mapper::Mirroring;
+use crate::rom::rom_file::RomFile;
+use crate::rom::rom_header::RomHeader;
+use std::cell::Cell;
+
+/// CPU cycles without PPU reads after which the MMC5 considers that rendering stopped.
+const PPU_IDLE_CYCLES: u8 = 3;
+
+/// The Nintendo MMC5 is the most powerful mapper ASIC Nintendo made for the NES, used in the
+/// ExROM boards. The iNES format assigns mapper 5 to MMC5.
+///
+/// The MMC5 has no scanline input, it snoops the PPU bus instead: three reads from the same
+/// nametable address mark the start of a scanline, and the reads after it follow the fixed
+/// PPU fetch pattern, telling apart background and sprite fetches.
+/// https://wiki.nesdev.com/w/index.php/MMC5
+pub struct Mapper5 {
+    pub header: RomHeader,
+    pub pgr_data: Box<[u8]>,
+    pub chr_data: Box<[u8]>,
+    pub prg_ram: Box<[u8]>,
+    pub exram: [u8; 0x400],
+    chr_ram: bool,
+
+    /// PRG mode ($5100) (0: 32KB; 1: 16KB; 2: 16KB + 8KB; 3: 8KB)
+    prg_mode: u8,
+    /// CHR mode ($5101) (0: 8KB; 1: 4KB; 2: 2KB; 3: 1KB)
+    chr_mode: u8,
+    /// PRG RAM protect ($5102 and $5103), writes need them to be $02 and $01
+    prg_ram_protect: [u8; 2],
+    /// Extended RAM mode ($5104) (0: nametable; 1: extended attributes; 2: RAM; 3: ROM)
+    exram_mode: u8,
+    /// Nametable mapping ($5105) DDCC BBAA (0: CIRAM page 0; 1: CIRAM page 1; 2: ExRAM; 3: fill)
+    nametable_mapping: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+    /// PRG bankswitching ($5113-$5117), bit 7 selects ROM over RAM
+    prg_banks: [u8; 5],
+    /// CHR bankswitching, sprite set A ($5120-$5127) followed by background set B ($5128-$512B).
+    /// The upper CHR bank bits are latched into bits 8-9 on write.
+    chr_banks: [u16; 12],
+    /// Upper CHR bank bits ($5130)
+    chr_upper: u8,
+    /// Whether the last CHR bank write went to set B
+    chr_set_b: bool,
+
+    /// Vertical split mode ($5200) ES-W WWWW
+    split_control: u8,
+    /// Vertical split scroll ($5201)
+    split_scroll: u8,
+    /// Vertical split 4KB CHR bank ($5202)
+    split_bank: u8,
+
+    irq_compare: u8,
+    irq_enabled: bool,
+    /// Cleared by reads of $5204
+    irq_pending: Cell<bool>,
+    multiplicand: u8,
+    multiplier: u8,
+
+    // #region PPU snooping
+    /// 8x16 sprites, from the CPU writes to PPUCTRL
+    large_sprites: bool,
+    in_frame: bool,
+    scanline: u16,
+    ppu_idle: u8,
+    last_address: u16,
+    repeat_count: u8,
+    /// Number of PPU reads since the start of the scanline
+    fetch_count: u16,
+    /// ExRAM byte of the background tile being fetched, in extended attribute mode
+    ext_attribute: u8,
+    /// Whether the background tile being fetched comes from the vertical split
+    split_tile: bool,
+    split_column: u16,
+    split_row: u16,
+    // #endregion
+    audio: Mmc5Audio,
+}
+
+impl Mapper5 {
+    pub fn new(rom: &mut RomFile) -> Self {
+        let chr_ram = !rom.header.has_chr_rom();
+        let chr_data = if chr_ram {
+            rom.chr_ram()
+        } else {
+            Box::from(rom.chr_data.as_ref())
+        };
+
+        Self {
+            header: rom.header,
+            pgr_data: Box::from(rom.pgr_data.as_ref()),
+            chr_data,
+            prg_ram: rom.prg_ram(0x10000), // Up to 64KB of PRG RAM
+            exram: [0; 0x400],
+            chr_ram,
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks: [0, 0, 0, 0, 0xFF],
+            chr_banks: [0; 12],
+            chr_upper: 0,
+            chr_set_b: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_compare: 0,
+            irq_enabled: false,
+            irq_pending: Cell::new(false),
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            large_sprites: false,
+            in_frame: false,
+            scanline: 0,
+            ppu_idle: 0,
+            last_address: 0,
+            repeat_count: 0,
+            fetch_count: 0,
+            ext_attribute: 0,
+            split_tile: false,
+            split_column: 0,
+            split_row: 0,
+            audio: Mmc5Audio::new(),
+        }
+    }
+
+    // #region PRG
+    /// Whether the address is mapped to ROM, with the 8KB bank it maps to.
+    /// https://wiki.nesdev.com/w/index.php/MMC5#PRG_mode_.28.245100.29
+    fn prg_bank(&self, addr: u16) -> (bool, usize) {
+        if addr < 0x8000 {
+            return (false, (self.prg_banks[0] & 0x07) as usize);
+        }
+
+        let slot = ((addr - 0x8000) / 0x2000) as usize;
+        let (index, mask) = match (self.prg_mode, slot) {
+            (0, _) => (4, 0x7C),
+            (1, 0..=1) | (2, 0..=1) => (2, 0x7E),
+            (1, _) => (4, 0x7E),
+            (2, _) | (3, _) => (1 + slot, 0x7F),
+            _ => unreachable!(),
+        };
+        let register = self.prg_banks[index];
+
+        // $5117 always selects ROM, whatever its bit 7
+        let rom = index == 4 || (register & 0x80) != 0;
+        let bank = (register & mask) as usize | (slot & !(mask as usize) & 0x03);
+        (rom, bank)
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0x02, 0x01]
+    }
+    // #endregion
+
+    // #region CHR
+    /// https://wiki.nesdev.com/w/index.php/MMC5#CHR_mode_.28.245101.29
+    fn chr_address(&self, addr: u16) -> usize {
+        let size = 0x2000 >> self.chr_mode;
+        let registers = 8 >> self.chr_mode;
+
+        let sprite_fetch = match self.fetch_position(addr) {
+            Some((index, _)) => (128..160).contains(&index),
+            None => false,
+        };
+        let set_a = !self.large_sprites || sprite_fetch || (!self.in_frame && !self.chr_set_b);
+
+        let (addr, register) = if set_a {
+            let slot = addr as usize / size;
+            (addr, (slot + 1) * registers - 1)
+        } else {
+            // The background set only covers 4KB, mirrored on both pattern tables
+            let addr = if self.chr_mode == 0 {
+                addr
+            } else {
+                addr & 0x0FFF
+            };
+            let slot = addr as usize / size;
+            (addr, 8 + (((slot + 1) * registers - 1) & 0x03))
+        };
+
+        (self.chr_banks[register] as usize * size + addr as usize % size) % self.chr_data.len()
+    }
+    // #endregion
+
+    // #region PPU snooping
+    fn detects_scanline(&self, addr: u16) -> bool {
+        (0x2000..0x3000).contains(&addr) && addr == self.last_address && self.repeat_count == 1
+    }
+
+    /// Index of the PPU read about to happen since the start of the scanline, with the
+    /// scanline being drawn. Only known while rendering.
+    fn fetch_position(&self, addr: u16) -> Option<(u16, u16)> {
+        if self.detects_scanline(addr) {
+            let scanline = if self.in_frame { self.scanline + 1 } else { 0 };
+            Some((0, scanline))
+        } else if self.in_frame {
+            Some((self.fetch_count, self.scanline))
+        } else {
+            None
+        }
+    }
+
+    /// Column and scanline of the background tile being fetched, with the step of the fetch
+    /// (0: nametable; 1: attribute; 2 and 3: pattern). The scanline starts with tile 2, tiles
+    /// 0 and 1 of the next scanline are fetched after the sprites.
+    fn background_fetch(&self, addr: u16) -> Option<(u16, u16, u16)> {
+        match self.fetch_position(addr) {
+            Some((index, scanline)) if index < 128 => Some((2 + index / 4, scanline, index % 4)),
+            Some((index, scanline)) if (160..168).contains(&index) => {
+                Some(((index - 160) / 4, scanline + 1, index % 4))
+            }
+            _ => None,
+        }
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/MMC5#Vertical_Split_Mode_.28.245200.29
+    fn in_split(&self, column: u16) -> bool {
+        let threshold = (self.split_control & 0x1F) as u16;
+        let column = column & 0x1F;
+
+        (self.split_control & 0x80) != 0
+            && self.exram_mode <= 1
+            && if (self.split_control & 0x40) != 0 {
+                column >= threshold
+            } else {
+                column < threshold
+            }
+    }
+
+    fn detect_scanline(&mut self) {
+        if self.in_frame {
+            self.scanline += 1;
+            if self.scanline == self.irq_compare as u16 {
+                self.irq_pending.set(true);
+            }
+        } else {
+            self.in_frame = true;
+            self.scanline = 0;
+            self.irq_pending.set(false);
+        }
+    }
+
+    fn leave_frame(&mut self) {
+        self.in_frame = false;
+        self.last_address = 0;
+        self.repeat_count = 0;
+    }
+    // #endregion
+
+    fn read_register(&self, addr: u16) -> u8 {
+        match addr {
+            0x5015 => self.audio.read_status(),
+            // IRQ status PI-- ----
+            0x5204 => (self.irq_pending.get() as u8) << 7 | (self.in_frame as u8) << 6,
+            0x5205 => (self.multiplicand as u16 * self.multiplier as u16) as u8,
+            0x5206 => ((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8,
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => self.exram[(addr - 0x5C00) as usize],
+            0x6000..=0xFFFF => {
+                let (rom, bank) = self.prg_bank(addr);
+                let offset = (addr & 0x1FFF) as usize;
+                if rom {
+                    self.pgr_data[(bank * 0x2000 + offset) % self.pgr_data.len()]
+                } else {
+                    self.prg_ram[(bank * 0x2000 + offset) % self.prg_ram.len()]
+                }
+            }
+            _ => 0,
+        }
+    }
+}
+
+impl Mapper for Mapper5 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        let value = self.read_register(addr);
+        if addr == 0x5204 {
+            // Reading the status acknowledges the IRQ
+            self.irq_pending.set(false);
+        }
+        value
+    }
+
+    fn peek_prg(&self, addr: u16) -> u8 {
+        self.read_register(addr)
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/MMC5#Registers
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x5000..=0x5015 => self.audio.write_register(addr, value),
+            0x5100 => self.prg_mode = value & 0x03,
+            0x5101 => self.chr_mode = value & 0x03,
+            0x5102 => self.prg_ram_protect[0] = value & 0x03,
+            0x5103 => self.prg_ram_protect[1] = value & 0x03,
+            0x5104 => self.exram_mode = value & 0x03,
+            0x5105 => self.nametable_mapping = value,
+            0x5106 => self.fill_tile = value,
+            0x5107 => self.fill_attribute = value & 0x03,
+            0x5113..=0x5117 => self.prg_banks[(addr - 0x5113) as usize] = value,
+            0x5120..=0x512B => {
+                self.chr_banks[(addr - 0x5120) as usize] =
+                    value as u16 | (self.chr_upper as u16) << 8;
+                self.chr_set_b = addr >= 0x5128;
+            }
+            0x5130 => self.chr_upper = value & 0x03,
+            0x5200 => self.split_control = value,
+            0x5201 => self.split_scroll = value,
+            0x5202 => self.split_bank = value,
+            0x5203 => self.irq_compare = value,
+            0x5204 => self.irq_enabled = (value & 0x80) != 0,
+            0x5205 => self.multiplicand = value,
+            0x5206 => self.multiplier = value,
+            0x5C00..=0x5FFF => {
+                let offset = (addr - 0x5C00) as usize;
+                match self.exram_mode {
+                    // Writes only go through while rendering, otherwise $00 is written
+                    0 | 1 => self.exram[offset] = if self.in_frame { value } else { 0 },
+                    2 => self.exram[offset] = value,
+                    _ => {}
+                }
+            }
+            0x6000..=0xFFFF => {
+                let (rom, bank) = self.prg_bank(addr);
+                if !rom && self.prg_ram_writable() {
+                    let address = (bank * 0x2000 + (addr & 0x1FFF) as usize) % self.prg_ram.len();
+                    self.prg_ram[address] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        if let Some((_, _, step)) = self.background_fetch(addr) {
+            if step >= 2 && self.split_tile {
+                // The split uses its own bank and fine Y scroll
+                let row = addr & 0x0FF8 | (self.split_row & 0x07);
+                let address = self.split_bank as usize * 0x1000 + row as usize;
+                return self.chr_data[address % self.chr_data.len()];
+            }
+
+            if step >= 2 && self.exram_mode == 1 {
+                // Extended attributes pick a 4KB bank for each tile: PPCC CCCC
+                let bank = (self.ext_attribute & 0x3F) as usize | (self.chr_upper as usize) << 6;
+                let address = bank * 0x1000 + (addr & 0x0FFF) as usize;
+                return self.chr_data[address % self.chr_data.len()];
+            }
+        }
+
+        self.chr_data[self.chr_address(addr)]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if self.chr_ram {
+            let address = self.chr_address(addr);
+            self.chr_data[address] = value;
+        }
+    }
+
+    fn clock(&mut self) {
+        self.audio.clock();
+
+        if self.ppu_idle < PPU_IDLE_CYCLES {
+            self.ppu_idle += 1;
+            if self.ppu_idle == PPU_IDLE_CYCLES {
+                self.leave_frame();
+            }
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_enabled && self.irq_pending.get()
+    }
+
+    fn ppu_register_write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x2000 => self.large_sprites = (value & 0x20) != 0,
+            0x2001 if (value & 0x18) == 0 => self.leave_frame(),
+            _ => {}
+        }
+    }
+
+    fn ppu_address(&mut self, addr: u16) {
+        self.ppu_idle = 0;
+
+        if let Some((column, scanline, 0)) = self.background_fetch(addr) {
+            self.ext_attribute = self.exram[(addr & 0x03FF) as usize];
+            self.split_tile = self.in_split(column);
+            self.split_column = column & 0x1F;
+            self.split_row = (self.split_scroll as u16 + scanline) % 240;
+        }
+
+        if self.detects_scanline(addr) {
+            self.detect_scanline();
+            self.fetch_count = 1;
+        } else {
+            self.fetch_count = self.fetch_count.saturating_add(1);
+        }
+
+        if addr == self.last_address {
+            self.repeat_count = self.repeat_count.saturating_add(1);
+        } else {
+            self.last_address = addr;
+            self.repeat_count = 0;
+        }
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        // ExRAM and fill mode nametables are answered by `read_nametable`
+        let page = |table: u8| (self.nametable_mapping >> (table * 2)) & 0x01;
+        Some(Mirroring::Custom([page(0), page(1), page(2), page(3)]))
+    }
+
+    fn read_nametable(&self, addr: u16) -> Option<u8> {
+        let offset = (addr & 0x03FF) as usize;
+
+        if let Some((column, scanline, step)) = self.background_fetch(addr) {
+            if step == 0 && self.in_split(column) {
+                let row = (self.split_scroll as u16 + scanline) % 240;
+                let tile = (row / 8) * 32 + (column & 0x1F);
+                return Some(self.exram[tile as usize]);
+            }
+
+            if step == 1 && self.split_tile {
+                let (row, column) = (self.split_row, self.split_column);
+                let attribute = self.exram[(0x3C0 + (row / 32) * 8 + column / 4) as usize];
+                let shift = ((row >> 4) & 0x01) * 4 + ((column >> 1) & 0x01) * 2;
+                return Some(((attribute >> shift) & 0x03) * 0x55);
+            }
+
+            if step == 1 && self.exram_mode == 1 {
+                return Some((self.ext_attribute >> 6) * 0x55);
+            }
+        }
+
+        match (self.nametable_mapping >> (((addr >> 10) & 0x03) * 2)) & 0x03 {
+            0 | 1 => None,
+            2 if self.exram_mode <= 1 => Some(self.exram[offset]),
+            2 => Some(0),
+            _ if offset >= 0x3C0 => Some(self.fill_attribute * 0x55),
+            _ => Some(self.fill_tile),
+        }
+    }
+
+    fn write_nametable(&mut self, addr: u16, value: u8) -> bool {
+        match (self.nametable_mapping >> (((addr >> 10) & 0x03) * 2)) & 0x03 {
+            0 | 1 => false,
+            2 => {
+                if self.exram_mode <= 1 {
+                    self.exram[(addr & 0x03FF) as usize] = value;
+                }
+                true
+            }
+            _ => true,
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper5;
+    use crate::console::NesConsole;
+    use crate::rom::mapper::Mapper;
+    use crate::rom::rom_file::RomFile;
+    use crate::rom::rom_header::RomHeader;
+
+    fn rom() -> RomFile {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = 8;
+        header[5] = 8;
+        header[6] = 0x50;
+        RomFile {
+            header: RomHeader::new(&header),
+            pgr_data: (0..8 * 0x4000).map(|i| (i / 0x2000) as u8).collect(),
+            chr_data: vec![0u8; 8 * 0x2000].into_boxed_slice(),
+            trainer: None,
+        }
+    }
+
+    fn mapper() -> Mapper5 {
+        Mapper5::new(&mut rom())
+    }
+
+    /// PPU reads of a rendered scanline, ending with the two dummy nametable reads
+    fn scanline(mapper: &mut Mapper5) {
+        for tile in 2..34 {
+            mapper.ppu_address(0x2000 + tile);
+            mapper.ppu_address(0x23C0 + tile / 4);
+            mapper.ppu_address(tile * 16);
+            mapper.ppu_address(tile * 16 + 8);
+        }
+        for _ in 0..8 {
+            mapper.ppu_address(0x2022);
+            mapper.ppu_address(0x2022);
+            mapper.ppu_address(0x1FF0);
+            mapper.ppu_address(0x1FF8);
+        }
+        for tile in 0..2 {
+            mapper.ppu_address(0x2000 + tile);
+            mapper.ppu_address(0x23C0);
+            mapper.ppu_address(tile * 16);
+            mapper.ppu_address(tile * 16 + 8);
+        }
+        mapper.ppu_address(0x2002);
+        mapper.ppu_address(0x2002);
+    }
+
+    #[test]
+    fn scanline_irq() {
+        let mut mapper = mapper();
+        mapper.write_prg(0x5203, 2);
+        mapper.write_prg(0x5204, 0x80);
+
+        scanline(&mut mapper); // Pre-render line
+        assert_eq!(mapper.read_prg(0x5204), 0x00);
+
+        scanline(&mut mapper); // Scanline 0
+        scanline(&mut mapper);
+        assert!(!mapper.irq_pending());
+        scanline(&mut mapper);
+        assert!(mapper.irq_pending());
+        assert_eq!(mapper.peek_prg(0x5204), 0xC0);
+
+        assert_eq!(mapper.read_prg(0x5204), 0xC0);
+        assert!(!mapper.irq_pending());
+
+        // Without PPU reads the MMC5 leaves the frame
+        for _ in 0..3 {
+            mapper.clock();
+        }
+        assert_eq!(mapper.read_prg(0x5204), 0x00);
+    }
+
+    #[test]
+    fn scanline_irq_from_ppu() {
+        let nes = NesConsole::new();
+        nes.bus.borrow_mut().connect_cartridge(rom()).unwrap();
+        let mapper = nes.bus.borrow().mapper.clone().unwrap();
+        {
+            let mut bus = nes.bus.borrow_mut();
+            bus.write(0x5203, 10);
+            bus.write(0x5204, 0x80);
+            bus.write(0x2001, 0x18); // Background and sprites
+        }
+
+        // From the pre-render line to the first dot of scanline 10
+        for _ in 0..341 * 11 + 1 {
+            nes.ppu.borrow_mut().tick();
+        }
+        assert!(!mapper.borrow().irq_pending());
+
+        // Scanlines are detected on the first nametable fetch
+        nes.ppu.borrow_mut().tick();
+        assert!(mapper.borrow().irq_pending());
+    }
+
+    #[test]
+    fn prg_banking_and_multiplier() {
+        let mut mapper = mapper();
+        assert_eq!(mapper.read_prg(0xE000), 15);
+
+        mapper.write_prg(0x5100, 2);
+        mapper.write_prg(0x5115, 0x85);
+        mapper.write_prg(0x5116, 0x87);
+        assert_eq!(mapper.read_prg(0x8000), 4);
+        assert_eq!(mapper.read_prg(0xA000), 5);
+        assert_eq!(mapper.read_prg(0xC000), 7);
+
+        // PRG RAM at $C000 needs both protect registers set
+        mapper.write_prg(0x5116, 0x01);
+        mapper.write_prg(0xC000, 0x42);
+        assert_eq!(mapper.read_prg(0xC000), 0x00);
+        mapper.write_prg(0x5102, 0x02);
+        mapper.write_prg(0x5103, 0x01);
+        mapper.write_prg(0xC000, 0x42);
+        assert_eq!(mapper.read_prg(0xC000), 0x42);
+
+        // $5117 maps ROM even with bit 7 clear, so the vectors stay in ROM
+        mapper.write_prg(0x5100, 0);
+        mapper.write_prg(0x5117, 0x04);
+        mapper.write_prg(0x8000, 0x42);
+        assert_eq!(mapper.read_prg(0x8000), 4);
+        assert_eq!(mapper.read_prg(0xFFFF), 7);
+
+        mapper.write_prg(0x5205, 200);
+        mapper.write_prg(0x5206, 100);
+        assert_eq!(mapper.read_prg(0x5205), (20000 & 0xFF) as u8);
+        assert_eq!(mapper.read_prg(0x5206), (20000 >> 8) as u8);
+    }
+}
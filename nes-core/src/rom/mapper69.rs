@@ -0,0 +1,256 @@
+use crate::apu::sunsoft5b::Sunsoft5bAudio;
+use crate::rom::mapper::Mapper;
+use crate::rom::mapper::Mirroring;
+use crate::rom::rom_file::RomFile;
+use crate::rom::rom_header::RomHeader;
+
+/// The Sunsoft FME-7 is an ASIC mapper with 8KB PRG banking, including the $6000 window, 1KB
+/// CHR banking and a CPU cycle IRQ counter. The Sunsoft 5B is the same mapper with extra audio.
+/// The iNES format assigns mapper 69 to both, the audio is always emulated.
+/// https://wiki.nesdev.com/w/index.php/Sunsoft_FME-7
+pub struct Mapper69 {
+    pub header: RomHeader,
+    pub pgr_data: Box<[u8]>,
+    pub chr_data: Box<[u8]>,
+    pub prg_ram: Box<[u8]>,
+    chr_ram: bool,
+
+    /// Command register ($8000-$9FFF) ---- CCCC
+    command: u8,
+    /// CHR banks (commands $0-$7)
+    chr_banks: [u8; 8],
+    /// PRG bank 0 at $6000 (command $8) ERbB BBBB
+    prg_ram_bank: u8,
+    /// PRG banks 1-3 at $8000, $A000 and $C000 (commands $9-$B) --bB BBBB
+    prg_banks: [u8; 3],
+    /// Mirroring (command $C) ---- --MM
+    mirroring: u8,
+
+    /// IRQ control (command $D) C--- ---T, counter and IRQ enable
+    irq_control: u8,
+    /// IRQ counter (commands $E and $F)
+    irq_counter: u16,
+    irq_pending: bool,
+
+    audio: Sunsoft5bAudio,
+}
+
+impl Mapper69 {
+    pub fn new(rom: &mut RomFile) -> Self {
+        let chr_ram = !rom.header.has_chr_rom();
+        let chr_data = if chr_ram {
+            rom.chr_ram()
+        } else {
+            Box::from(rom.chr_data.as_ref())
+        };
+
+        Self {
+            header: rom.header,
+            pgr_data: Box::from(rom.pgr_data.as_ref()),
+            chr_data,
+            prg_ram: rom.prg_ram(0x2000),
+            chr_ram,
+            command: 0,
+            chr_banks: [0; 8],
+            prg_ram_bank: 0,
+            prg_banks: [0; 3],
+            mirroring: 0,
+            irq_control: 0,
+            irq_counter: 0,
+            irq_pending: false,
+            audio: Sunsoft5bAudio::new(),
+        }
+    }
+
+    /// The $6000 window maps RAM instead of ROM when bit 6 is set
+    fn prg_ram_selected(&self) -> bool {
+        (self.prg_ram_bank & 0x40) != 0
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        (self.prg_ram_bank & 0x80) != 0
+    }
+
+    fn prg_address(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x6000..=0x7FFF => self.prg_ram_bank & 0x3F,
+            0x8000..=0xDFFF => self.prg_banks[((addr - 0x8000) / 0x2000) as usize] & 0x3F,
+            _ => (self.pgr_data.len() / 0x2000 - 1) as u8,
+        };
+
+        (bank as usize * 0x2000 + (addr & 0x1FFF) as usize) % self.pgr_data.len()
+    }
+
+    fn prg_ram_address(&self, addr: u16) -> usize {
+        let bank = (self.prg_ram_bank & 0x3F) as usize;
+        (bank * 0x2000 + (addr - 0x6000) as usize) % self.prg_ram.len()
+    }
+
+    fn chr_address(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[(addr / 0x400) as usize] as usize;
+        (bank * 0x400 + (addr & 0x03FF) as usize) % self.chr_data.len()
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/Sunsoft_FME-7#Parameter_Register_.28.24A000-.24BFFF.29
+    fn write_parameter(&mut self, value: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks[self.command as usize] = value,
+            0x8 => self.prg_ram_bank = value,
+            0x9..=0xB => self.prg_banks[(self.command - 0x9) as usize] = value,
+            0xC => self.mirroring = value & 0x03,
+            0xD => {
+                self.irq_control = value;
+                self.irq_pending = false;
+            }
+            0xE => self.irq_counter = (self.irq_counter & 0xFF00) | value as u16,
+            _ => self.irq_counter = (self.irq_counter & 0x00FF) | (value as u16) << 8,
+        }
+    }
+}
+
+impl Mapper for Mapper69 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x5FFF => 0,
+            0x6000..=0x7FFF if self.prg_ram_selected() => {
+                if self.prg_ram_enabled() {
+                    self.prg_ram[self.prg_ram_address(addr)]
+                } else {
+                    0
+                }
+            }
+            0x6000..=0xFFFF => self.pgr_data[self.prg_address(addr)],
+        }
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/Sunsoft_FME-7#Registers
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x5FFF => {}
+            0x6000..=0x7FFF => {
+                if self.prg_ram_selected() && self.prg_ram_enabled() {
+                    let address = self.prg_ram_address(addr);
+                    self.prg_ram[address] = value;
+                }
+            }
+            0x8000..=0x9FFF => self.command = value & 0x0F,
+            0xA000..=0xBFFF => self.write_parameter(value),
+            0xC000..=0xDFFF => self.audio.write_address(value),
+            0xE000..=0xFFFF => self.audio.write_data(value),
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr_data[self.chr_address(addr)]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if self.chr_ram {
+            let address = self.chr_address(addr);
+            self.chr_data[address] = value;
+        }
+    }
+
+    /// The counter decrements on every CPU cycle and fires when it wraps from $0000 to $FFFF
+    fn clock(&mut self) {
+        if (self.irq_control & 0x80) != 0 {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xFFFF && (self.irq_control & 0x01) != 0 {
+                self.irq_pending = true;
+            }
+        }
+
+        self.audio.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        match self.mirroring {
+            0 => Some(Mirroring::Vertical),
+            1 => Some(Mirroring::Horizontal),
+            2 => Some(Mirroring::SingleScreenLower),
+            _ => Some(Mirroring::SingleScreenUpper),
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper69;
+    use crate::rom::mapper::Mapper;
+    use crate::rom::rom_file::RomFile;
+    use crate::rom::rom_header::RomHeader;
+
+    fn fme7() -> Mapper69 {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = 16;
+        header[5] = 16;
+        header[6] = 0x50;
+        header[7] = 0x40;
+        let pgr_data: Vec<u8> = (0..0x40000).map(|i| (i / 0x2000) as u8).collect();
+        let mut rom = RomFile {
+            header: RomHeader::new(&header),
+            pgr_data: pgr_data.into_boxed_slice(),
+            chr_data: vec![0u8; 0x20000].into_boxed_slice(),
+            trainer: None,
+        };
+        Mapper69::new(&mut rom)
+    }
+
+    fn command(mapper: &mut Mapper69, command: u8, parameter: u8) {
+        mapper.write_prg(0x8000, command);
+        mapper.write_prg(0xA000, parameter);
+    }
+
+    #[test]
+    fn prg_banking() {
+        let mut mapper = fme7();
+        command(&mut mapper, 0x9, 3);
+        command(&mut mapper, 0xB, 7);
+        assert_eq!(mapper.read_prg(0x8000), 3);
+        assert_eq!(mapper.read_prg(0xC000), 7);
+        assert_eq!(mapper.read_prg(0xE000), 31);
+
+        // ROM, then RAM in the $6000 window
+        command(&mut mapper, 0x8, 5);
+        assert_eq!(mapper.read_prg(0x6000), 5);
+        command(&mut mapper, 0x8, 0xC0);
+        mapper.write_prg(0x6000, 0x42);
+        assert_eq!(mapper.read_prg(0x6000), 0x42);
+    }
+
+    #[test]
+    fn cycle_irq() {
+        let mut mapper = fme7();
+        command(&mut mapper, 0xE, 2);
+        command(&mut mapper, 0xF, 0);
+        command(&mut mapper, 0xD, 0x81);
+
+        // 1, 0, then $FFFF
+        for _ in 0..2 {
+            mapper.clock();
+        }
+        assert!(!mapper.irq_pending());
+        mapper.clock();
+        assert!(mapper.irq_pending());
+
+        command(&mut mapper, 0xD, 0x81); // Acknowledge
+        assert!(!mapper.irq_pending());
+    }
+}
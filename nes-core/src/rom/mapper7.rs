@@ -0,0 +1,72 @@
+use crate::rom::mapper::Mapper;
+use crate::rom::mapper::Mirroring;
+use crate::rom::rom_file::RomFile;
+use crate::rom::rom_header::RomHeader;
+
+/// The generic designation AxROM refers to Nintendo cartridge boards NES-ANROM, NES-AN1ROM,
+/// NES-AMROM, NES-AOROM, and clone boards. The iNES format assigns mapper 7 to AxROM.
+/// https://wiki.nesdev.com/w/index.php/AxROM
+pub struct Mapper7 {
+    pub header: RomHeader,
+    pub pgr_data: Box<[u8]>,
+    pub chr_data: Box<[u8]>,
+    /// xxxM xPPP
+    bank_select: u8,
+}
+
+impl Mapper7 {
+    pub fn new(rom: &mut RomFile) -> Self {
+        let chr_data = if !rom.header.has_chr_rom() {
+            rom.chr_ram()
+        } else {
+            Box::from(rom.chr_data.as_ref())
+        };
+
+        Self {
+            header: rom.header,
+            pgr_data: Box::from(rom.pgr_data.as_ref()),
+            chr_data,
+            bank_select: 0,
+        }
+    }
+}
+
+impl Mapper for Mapper7 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x7FFF => 0,
+            // CPU $8000-$FFFF: 32 KB switchable PRG ROM bank
+            0x8000..=0xFFFF => {
+                let bank = (self.bank_select & 0x07) as usize;
+                self.pgr_data[(bank * 0x8000 + (addr - 0x8000) as usize) % self.pgr_data.len()]
+            }
+        }
+    }
+
+    /// ANROM and AOROM have no bus conflicts, which is what most AxROM games expect, so the
+    /// AMROM conflicts are not emulated.
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        if addr >= 0x8000 {
+            self.bank_select = value;
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr_data[addr as usize % self.chr_data.len()]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if !self.header.has_chr_rom() {
+            self.chr_data[addr as usize] = value;
+        }
+    }
+
+    /// Bit 4 selects the 1KB VRAM page used for all nametables
+    fn mirroring(&self) -> Option<Mirroring> {
+        if (self.bank_select & 0x10) == 0 {
+            Some(Mirroring::SingleScreenLower)
+        } else {
+            Some(Mirroring::SingleScreenUpper)
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use crate::apu::vrc7::Vrc7Audio;
+use crate::rom::mapper::Mapper;
+use crate::rom::mapper::Mirroring;
+use crate::rom::rom_file::RomFile;
+use crate::rom::rom_header::RomHeader;
+use crate::rom::vrc_irq::VrcIrq;
+
+/// The Konami VRC7 is an ASIC mapper with 8KB PRG banking, 1KB CHR banking, the VRC IRQ counter
+/// and an FM synthesizer. The iNES format assigns mapper 85 to it. Boards pick the second
+/// register of each pair with A4 (VRC7a) or A3 (VRC7b), NES 2.0 submappers tell them apart,
+/// otherwise both lines are decoded.
+/// https://wiki.nesdev.com/w/index.php/VRC7
+pub struct Mapper85 {
+    pub header: RomHeader,
+    pub pgr_data: Box<[u8]>,
+    pub chr_data: Box<[u8]>,
+    pub prg_ram: Box<[u8]>,
+    chr_ram: bool,
+
+    /// CPU address lines selecting the second register of each pair
+    register_lines: u16,
+
+    /// PRG banks at $8000 ($8000), $A000 ($8010) and $C000 ($9000) --PP PPPP
+    prg_banks: [u8; 3],
+    /// CHR banks ($A000-$D010)
+    chr_banks: [u8; 8],
+    /// Control ($E000) RS-- --MM, WRAM enable, sound reset and mirroring
+    control: u8,
+
+    irq: VrcIrq,
+    audio: Vrc7Audio,
+}
+
+impl Mapper85 {
+    pub fn new(rom: &mut RomFile) -> Self {
+        let chr_ram = !rom.header.has_chr_rom();
+        let chr_data = if chr_ram {
+            rom.chr_ram()
+        } else {
+            Box::from(rom.chr_data.as_ref())
+        };
+
+        let register_lines = match rom.header.get_submapper_id() {
+            1 => 0x08, // VRC7b
+            2 => 0x10, // VRC7a
+            _ => 0x18,
+        };
+
+        Self {
+            header: rom.header,
+            pgr_data: Box::from(rom.pgr_data.as_ref()),
+            chr_data,
+            prg_ram: rom.prg_ram(0x2000),
+            chr_ram,
+            register_lines,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            control: 0,
+            irq: VrcIrq::new(),
+            audio: Vrc7Audio::new(),
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        (self.control & 0x80) != 0
+    }
+
+    /// The sound reset bit keeps the synthesizer silent
+    fn sound_enabled(&self) -> bool {
+        (self.control & 0x40) == 0
+    }
+
+    fn prg_address(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x8000..=0xDFFF => self.prg_banks[((addr - 0x8000) / 0x2000) as usize] as usize,
+            _ => self.pgr_data.len() / 0x2000 - 1,
+        };
+
+        (bank * 0x2000 + (addr & 0x1FFF) as usize) % self.pgr_data.len()
+    }
+
+    fn chr_address(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[(addr / 0x400) as usize] as usize;
+        (bank * 0x400 + (addr & 0x03FF) as usize) % self.chr_data.len()
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/VRC7#Registers
+    fn write_register(&mut self, addr: u16, value: u8) {
+        let second = (addr & self.register_lines) != 0;
+
+        match (addr & 0xF000, second) {
+            (0x8000, false) => self.prg_banks[0] = value & 0x3F,
+            (0x8000, true) => self.prg_banks[1] = value & 0x3F,
+            (0x9000, _) => match addr & 0x0030 {
+                0x0010 => self.audio.write_address(value),
+                0x0030 => self.audio.write_data(value),
+                _ => self.prg_banks[2] = value & 0x3F,
+            },
+            (0xA000..=0xD000, _) => {
+                let index = ((addr - 0xA000) / 0x1000 * 2) as usize + second as usize;
+                self.chr_banks[index] = value;
+            }
+            (0xE000, false) => self.control = value,
+            (0xE000, true) => self.irq.write_latch(value),
+            (0xF000, false) => self.irq.write_control(value),
+            (0xF000, true) => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Mapper85 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x5FFF => 0,
+            0x6000..=0x7FFF => {
+                if self.prg_ram_enabled() {
+                    self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()]
+                } else {
+                    0
+                }
+            }
+            0x8000..=0xFFFF => self.pgr_data[self.prg_address(addr)],
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x5FFF => {}
+            0x6000..=0x7FFF => {
+                if self.prg_ram_enabled() {
+                    self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()] = value;
+                }
+            }
+            0x8000..=0xFFFF => self.write_register(addr, value),
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr_data[self.chr_address(addr)]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if self.chr_ram {
+            let address = self.chr_address(addr);
+            self.chr_data[address] = value;
+        }
+    }
+
+    fn clock(&mut self) {
+        self.irq.clock();
+
+        if self.sound_enabled() {
+            self.audio.clock();
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        match self.control & 0x03 {
+            0 => Some(Mirroring::Vertical),
+            1 => Some(Mirroring::Horizontal),
+            2 => Some(Mirroring::SingleScreenLower),
+            _ => Some(Mirroring::SingleScreenUpper),
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        if self.sound_enabled() {
+            self.audio.output()
+        } else {
+            0.0
+        }
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper85;
+    use crate::rom::mapper::Mapper;
+    use crate::rom::rom_file::RomFile;
+    use crate::rom::rom_header::RomHeader;
+
+    #[test]
+    fn register_lines() {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = 16;
+        header[5] = 16;
+        header[6] = 0x50;
+        header[7] = 0x50;
+        let pgr_data: Vec<u8> = (0..0x40000).map(|i| (i / 0x2000) as u8).collect();
+        let chr_data: Vec<u8> = (0..0x20000).map(|i| (i / 0x400) as u8).collect();
+        let mut rom = RomFile {
+            header: RomHeader::new(&header),
+            pgr_data: pgr_data.into_boxed_slice(),
+            chr_data: chr_data.into_boxed_slice(),
+            trainer: None,
+        };
+        let mut mapper = Mapper85::new(&mut rom);
+
+        // VRC7a and VRC7b addresses of the $A000 PRG bank
+        mapper.write_prg(0x8010, 4);
+        assert_eq!(mapper.read_prg(0xA000), 4);
+        mapper.write_prg(0x8008, 5);
+        assert_eq!(mapper.read_prg(0xA000), 5);
+
+        mapper.write_prg(0x9000, 6);
+        assert_eq!(mapper.read_prg(0xC000), 6);
+        assert_eq!(mapper.read_prg(0xE000), 31);
+
+        mapper.write_prg(0xD010, 0x17);
+        assert_eq!(mapper.read_chr(0x1C00), 0x17);
+
+        // The audio ports don't touch the PRG bank
+        mapper.write_prg(0x9010, 0x30);
+        mapper.write_prg(0x9030, 0x10);
+        assert_eq!(mapper.read_prg(0xC000), 6);
+    }
+}
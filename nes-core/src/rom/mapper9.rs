@@ -0,0 +1,210 @@
+use crate::rom::mapper::Mapper;
+use crate::rom::mapper::Mirroring;
+use crate::rom::rom_file::RomError;
+use crate::rom::rom_file::RomFile;
+use crate::rom::rom_header::RomHeader;
+
+/// The MMC2 (mapper 9, used by Punch-Out!!) and MMC4 (mapper 10, used by Fire Emblem) are
+/// Nintendo mapper ASICs whose CHR banks switch automatically when the PPU fetches tile $FD or $FE.
+/// https://wiki.nesdev.com/w/index.php/MMC2
+/// https://wiki.nesdev.com/w/index.php/MMC4
+pub struct Mapper9 {
+    pub header: RomHeader,
+    pub pgr_data: Box<[u8]>,
+    pub chr_data: Box<[u8]>,
+    pub prg_ram: Box<[u8]>,
+    /// MMC4 has 16KB PRG banks, PRG RAM and wider latch trigger ranges
+    mmc4: bool,
+
+    prg_bank: u8,
+    /// 4KB CHR banks, indexed by [pattern table][latch]
+    chr_banks: [[u8; 2]; 2],
+    /// Latch of each pattern table (false: $FD; true: $FE)
+    latches: [bool; 2],
+    /// Mirroring (0: vertical; 1: horizontal)
+    mirroring: u8,
+}
+
+impl Mapper9 {
+    pub fn new(rom: &mut RomFile) -> Result<Self, RomError> {
+        // The fixed banks take the last 16KB (MMC4) or 24KB (MMC2) of PRG ROM
+        let mmc4 = rom.header.get_mapper_id() == 10;
+        let fixed_size = if mmc4 { 0x4000 } else { 0x6000 };
+        if rom.pgr_data.len() < fixed_size {
+            return Err(RomError::InconsistentSizes {
+                prg_rom: rom.pgr_data.len(),
+                chr_rom: rom.chr_data.len(),
+            });
+        }
+
+        let chr_data = if !rom.header.has_chr_rom() {
+            rom.chr_ram()
+        } else {
+            Box::from(rom.chr_data.as_ref())
+        };
+
+        Ok(Self {
+            header: rom.header,
+            pgr_data: Box::from(rom.pgr_data.as_ref()),
+            chr_data,
+            prg_ram: rom.prg_ram(0x2000),
+            mmc4,
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [true; 2],
+            mirroring: 0,
+        })
+    }
+
+    fn prg_address(&self, addr: u16) -> usize {
+        let len = self.pgr_data.len();
+        let address = if self.mmc4 {
+            match addr {
+                // CPU $8000-$BFFF: 16 KB switchable PRG ROM bank
+                0x8000..=0xBFFF => self.prg_bank as usize * 0x4000 + (addr & 0x3FFF) as usize,
+                // CPU $C000-$FFFF: 16 KB PRG ROM bank, fixed to the last bank
+                _ => len - 0x4000 + (addr & 0x3FFF) as usize,
+            }
+        } else {
+            match addr {
+                // CPU $8000-$9FFF: 8 KB switchable PRG ROM bank
+                0x8000..=0x9FFF => self.prg_bank as usize * 0x2000 + (addr & 0x1FFF) as usize,
+                // CPU $A000-$FFFF: Three 8 KB PRG ROM banks, fixed to the last three banks
+                _ => len - 0x6000 + (addr - 0xA000) as usize,
+            }
+        };
+
+        address % len
+    }
+
+    fn chr_address(&self, addr: u16) -> usize {
+        let table = (addr >> 12) as usize & 0x01;
+        let bank = self.chr_banks[table][self.latches[table] as usize] as usize;
+        (bank * 0x1000 + (addr & 0x0FFF) as usize) % self.chr_data.len()
+    }
+}
+
+impl Mapper for Mapper9 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x5FFF => 0,
+            0x6000..=0x7FFF if self.mmc4 => {
+                self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()]
+            }
+            0x6000..=0x7FFF => 0,
+            0x8000..=0xFFFF => self.pgr_data[self.prg_address(addr)],
+        }
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/MMC2#Registers
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.mmc4 => {
+                self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()] = value
+            }
+            0xA000..=0xAFFF => self.prg_bank = value & 0x0F,
+            0xB000..=0xBFFF => self.chr_banks[0][0] = value & 0x1F,
+            0xC000..=0xCFFF => self.chr_banks[0][1] = value & 0x1F,
+            0xD000..=0xDFFF => self.chr_banks[1][0] = value & 0x1F,
+            0xE000..=0xEFFF => self.chr_banks[1][1] = value & 0x1F,
+            0xF000..=0xFFFF => self.mirroring = value & 0x01,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr_data[self.chr_address(addr)]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if !self.header.has_chr_rom() {
+            let address = self.chr_address(addr);
+            self.chr_data[address] = value;
+        }
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/MMC2#CHR_banking
+    fn ppu_address(&mut self, addr: u16) {
+        // MMC2 only triggers the first pattern table latch on the exact addresses
+        let exact = !self.mmc4 && addr < 0x1000;
+
+        match addr & 0x0FFF {
+            0x0FD8 => self.latches[(addr >> 12) as usize] = false,
+            0x0FE8 => self.latches[(addr >> 12) as usize] = true,
+            0x0FD9..=0x0FDF if !exact => self.latches[(addr >> 12) as usize] = false,
+            0x0FE9..=0x0FEF if !exact => self.latches[(addr >> 12) as usize] = true,
+            _ => {}
+        }
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        if self.mirroring == 0 {
+            Some(Mirroring::Vertical)
+        } else {
+            Some(Mirroring::Horizontal)
+        }
+    }
+
+    /// Only the MMC4 has PRG RAM
+    fn prg_ram(&self) -> Option<&[u8]> {
+        if self.mmc4 {
+            Some(&self.prg_ram)
+        } else {
+            None
+        }
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        if self.mmc4 {
+            Some(&mut self.prg_ram)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper9;
+    use crate::rom::mapper::Mapper;
+    use crate::rom::rom_file::RomFile;
+    use crate::rom::rom_header::RomHeader;
+
+    #[test]
+    fn chr_latches() {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = 8;
+        header[5] = 4;
+        header[6] = 0x90;
+        let mut rom = RomFile {
+            header: RomHeader::new(&header),
+            pgr_data: vec![0u8; 8 * 0x4000].into_boxed_slice(),
+            chr_data: (0..4 * 0x2000).map(|i| (i / 0x1000) as u8).collect(),
+            trainer: None,
+        };
+        let mut mapper = Mapper9::new(&mut rom).unwrap();
+        assert_eq!(mapper.prg_address(0xA000), 0x1A000);
+        assert_eq!(mapper.prg_address(0xFFFF), 0x1FFFF);
+        assert!(mapper.save_data().is_none()); // MMC2 has no PRG RAM
+
+        mapper.write_prg(0xB000, 1); // $0000 with latch $FD
+        mapper.write_prg(0xC000, 2); // $0000 with latch $FE
+        mapper.write_prg(0xD000, 3); // $1000 with latch $FD
+        mapper.write_prg(0xE000, 4); // $1000 with latch $FE
+        assert_eq!(mapper.read_chr(0x0000), 2);
+        assert_eq!(mapper.read_chr(0x1000), 4);
+
+        mapper.ppu_address(0x0FD8);
+        assert_eq!(mapper.read_chr(0x0000), 1);
+
+        // MMC2 ignores the rest of the tile for the first pattern table
+        mapper.ppu_address(0x0FE9);
+        assert_eq!(mapper.read_chr(0x0000), 1);
+
+        mapper.ppu_address(0x1FDF);
+        assert_eq!(mapper.read_chr(0x1000), 3);
+        mapper.ppu_address(0x1FE8);
+        assert_eq!(mapper.read_chr(0x1000), 4);
+    }
+}
@@ -1,4 +1,20 @@
 pub mod mapper;
 pub mod mapper0;
+pub mod mapper1;
+pub mod mapper11;
+pub mod mapper19;
+pub mod mapper2;
+pub mod mapper21;
+pub mod mapper24;
+pub mod mapper3;
+pub mod mapper34;
+pub mod mapper4;
+pub mod mapper5;
+pub mod mapper66;
+pub mod mapper69;
+pub mod mapper7;
+pub mod mapper85;
+pub mod mapper9;
 pub mod rom_file;
 pub mod rom_header;
+pub mod vrc_irq;
@@ -2,35 +2,134 @@ extern crate bytesize;
 
 use crate::rom::mapper::Mapper;
 use crate::rom::mapper0::Mapper0;
+use crate::rom::mapper1::Mapper1;
+use crate::rom::mapper11::Mapper11;
+use crate::rom::mapper19::Mapper19;
+use crate::rom::mapper2::Mapper2;
+use crate::rom::mapper21::Mapper21;
+use crate::rom::mapper24::Mapper24;
+use crate::rom::mapper3::Mapper3;
+use crate::rom::mapper34::Mapper34;
+use crate::rom::mapper4::Mapper4;
+use crate::rom::mapper5::Mapper5;
+use crate::rom::mapper66::Mapper66;
+use crate::rom::mapper69::Mapper69;
+use crate::rom::mapper7::Mapper7;
+use crate::rom::mapper85::Mapper85;
+use crate::rom::mapper9::Mapper9;
 use crate::rom::rom_header::RomHeader;
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
+use std::io;
 use std::io::BufReader;
 use std::io::Read;
 use std::path::Path;
+use std::path::PathBuf;
+
+/// Reasons a ROM image can't be loaded
+#[derive(Debug)]
+pub enum RomError {
+    /// The file couldn't be read
+    Io(io::Error),
+    /// The image is shorter than its header says
+    Truncated { expected: usize, actual: usize },
+    /// The image doesn't start with "NES\x1A"
+    BadMagic,
+    /// The header asks for a mapper that isn't emulated
+    UnsupportedMapper(u16),
+    /// The header describes ROM sizes no cartridge could have, like an empty PRG ROM
+    InconsistentSizes { prg_rom: usize, chr_rom: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Io(e) => write!(f, "{}", e),
+            RomError::Truncated { expected, actual } => write!(
+                f,
+                "ROM is truncated, expected {} bytes but got {}",
+                expected, actual
+            ),
+            RomError::BadMagic => write!(f, "Invalid iNES rom file"),
+            RomError::UnsupportedMapper(id) => write!(f, "Mapper {} not implemented", id),
+            RomError::InconsistentSizes { prg_rom, chr_rom } => write!(
+                f,
+                "Invalid ROM sizes, {} bytes of PGR ROM and {} bytes of CHR ROM",
+                prg_rom, chr_rom
+            ),
+        }
+    }
+}
+
+impl Error for RomError {}
+
+impl From<io::Error> for RomError {
+    fn from(e: io::Error) -> Self {
+        RomError::Io(e)
+    }
+}
+
+/// Size of the trainer, loaded at $7000-$71FF
+const TRAINER_SIZE: usize = 0x200;
 
 pub struct RomFile {
     pub header: RomHeader,
+    pub trainer: Option<Box<[u8]>>,
     pub pgr_data: Box<[u8]>,
     pub chr_data: Box<[u8]>,
 }
 
 impl RomFile {
-    pub fn new(buffer: &mut dyn Read) -> Self {
-        let header_buf = &mut [0u8; 16];
+    pub fn new(buffer: &mut dyn Read) -> Result<Self, RomError> {
+        let mut data = Vec::new();
+        buffer.read_to_end(&mut data)?;
 
-        buffer.read_exact(header_buf).unwrap();
+        if data.len() < 16 {
+            return Err(RomError::Truncated {
+                expected: 16,
+                actual: data.len(),
+            });
+        }
 
-        let header = RomHeader::new(header_buf);
+        let header = RomHeader::new(&data[..16]);
 
         // println!("Loaded ROM header: {:?}", header);
 
-        let mut pgr_data = vec![0u8; 0x4000 * header.prg_rom_size as usize].into_boxed_slice();
-        let mut chr_data = vec![0u8; 0x2000 * header.chr_rom_size as usize].into_boxed_slice();
+        if !header.is_valid() {
+            return Err(RomError::BadMagic);
+        }
+
+        // NES 2.0 exponent sizes can be any number of bytes, mappers mirror ROMs that don't fill
+        // their banks, but the CPU needs at least one 8KB bank to run from
+        let prg_rom = header.prg_rom_bytes();
+        let chr_rom = header.chr_rom_bytes();
+        if prg_rom < 0x2000 {
+            return Err(RomError::InconsistentSizes { prg_rom, chr_rom });
+        }
 
-        assert!(header.is_valid(), "Invalid iNES rom file");
+        let trainer_size = if header.has_trainer() {
+            TRAINER_SIZE
+        } else {
+            0
+        };
+        let size = prg_rom.saturating_add(chr_rom).saturating_add(trainer_size);
+        let data = &data[16..];
+        if data.len() < size {
+            return Err(RomError::Truncated {
+                expected: size.saturating_add(16),
+                actual: data.len() + 16,
+            });
+        }
 
-        buffer.read_exact(pgr_data.as_mut()).unwrap();
-        buffer.read_exact(chr_data.as_mut()).unwrap();
+        let (trainer, data) = data.split_at(trainer_size);
+        let trainer = if header.has_trainer() {
+            Some(Box::from(trainer))
+        } else {
+            None
+        };
+        let pgr_data: Box<[u8]> = Box::from(&data[..prg_rom]);
+        let chr_data: Box<[u8]> = Box::from(&data[prg_rom..prg_rom + chr_rom]);
 
         println!(
             "Loaded ROM with {} of PGR ROM and {} of CHR ROM",
@@ -38,33 +137,212 @@ impl RomFile {
             bytesize::to_string(chr_data.len() as u64, true),
         );
 
-        RomFile {
+        Ok(RomFile {
             header,
+            trainer,
             pgr_data,
             chr_data,
-        }
+        })
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> Self {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RomError> {
         Self::new(&mut BufReader::new(bytes))
     }
 
-    pub fn from_file(path: &Path) -> Self {
-        Self::new(&mut File::open(&path).unwrap())
+    pub fn from_file(path: &Path) -> Result<Self, RomError> {
+        Self::new(&mut File::open(path)?)
     }
 
-    pub fn get_mapper(&mut self) -> Box<dyn Mapper> {
-        match self.header.get_mapper_id() {
-            0 => Box::new(Mapper0::new(self)) as Box<dyn Mapper>,
-            id => panic!("Mapper {} not implemented", id),
+    /// Save file kept next to the ROM for battery-backed memory, `game.nes` saves to `game.sav`
+    pub fn save_path(rom_path: &Path) -> PathBuf {
+        rom_path.with_extension("sav")
+    }
+
+    /// PRG RAM for mappers to own, with the trainer copied to $7000 when the ROM has one. NES 2.0
+    /// headers give the exact size, `size` is what the board usually has and is used when the
+    /// header doesn't say, or when an iNES header says less since byte 8 is rarely filled in.
+    pub fn prg_ram(&self, size: usize) -> Box<[u8]> {
+        let header_size = self.header.prg_ram_bytes() + self.header.prg_nvram_bytes();
+        let size = if self.header.is_nes2() && header_size != 0 {
+            header_size
+        } else {
+            header_size.max(size)
+        };
+
+        let mut prg_ram = vec![0u8; size].into_boxed_slice();
+        if let Some(trainer) = &self.trainer {
+            // RAM smaller than 8KB is mirrored, $7000 lands at the start of one of the copies
+            let start = 0x1000 % size.max(1);
+            if start + TRAINER_SIZE <= size {
+                prg_ram[start..start + TRAINER_SIZE].copy_from_slice(trainer);
+            }
+        }
+        prg_ram
+    }
+
+    /// CHR RAM for mappers without CHR ROM, the size from the header but never less than the
+    /// 8KB of pattern tables the PPU addresses
+    pub fn chr_ram(&self) -> Box<[u8]> {
+        let size = self.header.chr_ram_bytes() + self.header.chr_nvram_bytes();
+        vec![0u8; size.max(0x2000)].into_boxed_slice()
+    }
+
+    pub fn get_mapper(&mut self) -> Result<Box<dyn Mapper>, RomError> {
+        let mapper = match self.header.get_mapper_id() {
+            0 => Box::new(Mapper0::new(self)?) as Box<dyn Mapper>,
+            1 => Box::new(Mapper1::new(self)?) as Box<dyn Mapper>,
+            2 => Box::new(Mapper2::new(self)?) as Box<dyn Mapper>,
+            3 => Box::new(Mapper3::new(self)) as Box<dyn Mapper>,
+            4 => Box::new(Mapper4::new(self)?) as Box<dyn Mapper>,
+            5 => Box::new(Mapper5::new(self)) as Box<dyn Mapper>,
+            7 => Box::new(Mapper7::new(self)) as Box<dyn Mapper>,
+            9 | 10 => Box::new(Mapper9::new(self)?) as Box<dyn Mapper>,
+            11 => Box::new(Mapper11::new(self)) as Box<dyn Mapper>,
+            19 => Box::new(Mapper19::new(self)) as Box<dyn Mapper>,
+            21 | 22 | 23 | 25 => Box::new(Mapper21::new(self)?) as Box<dyn Mapper>,
+            24 | 26 => Box::new(Mapper24::new(self)) as Box<dyn Mapper>,
+            34 => Box::new(Mapper34::new(self)) as Box<dyn Mapper>,
+            66 => Box::new(Mapper66::new(self)) as Box<dyn Mapper>,
+            69 => Box::new(Mapper69::new(self)) as Box<dyn Mapper>,
+            85 => Box::new(Mapper85::new(self)) as Box<dyn Mapper>,
+            id => return Err(RomError::UnsupportedMapper(id)),
+        };
+
+        let trainer_mapped = mapper
+            .prg_ram()
+            .is_some_and(|prg_ram| prg_ram.len() >= TRAINER_SIZE);
+        if self.trainer.is_some() && !trainer_mapped {
+            println!("Ignoring the ROM's trainer, the mapper has no PRG RAM at $7000");
         }
+
+        Ok(mapper)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::RomError;
+    use super::RomFile;
+
+    const NESTEST: &[u8] = include_bytes!("../../test/nestest.nes");
+
     #[test]
     fn rom_file_load() {
-        super::RomFile::from_bytes(include_bytes!("../../test/nestest.nes"));
+        RomFile::from_bytes(NESTEST).unwrap();
+    }
+
+    #[test]
+    fn rom_file_errors() {
+        match RomFile::from_bytes(&NESTEST[..0x1000]) {
+            Err(RomError::Truncated { expected, actual }) => {
+                assert_eq!(expected, NESTEST.len());
+                assert_eq!(actual, 0x1000);
+            }
+            _ => panic!("Expected a truncated ROM"),
+        }
+
+        let mut rom = NESTEST.to_vec();
+        rom[3] = 0;
+        assert!(matches!(RomFile::from_bytes(&rom), Err(RomError::BadMagic)));
+
+        let mut rom = NESTEST.to_vec();
+        rom[4] = 0;
+        assert!(matches!(
+            RomFile::from_bytes(&rom),
+            Err(RomError::InconsistentSizes { prg_rom: 0, .. })
+        ));
+
+        let mut rom = NESTEST.to_vec();
+        rom[6] = 0xF0;
+        rom[7] = 0xF0;
+        let mut rom = RomFile::from_bytes(&rom).unwrap();
+        assert!(matches!(
+            rom.get_mapper(),
+            Err(RomError::UnsupportedMapper(255))
+        ));
+
+        // MMC2 with less PRG ROM than its fixed banks
+        let mut rom = NESTEST.to_vec();
+        rom[6] = 0x90;
+        let mut rom = RomFile::from_bytes(&rom).unwrap();
+        assert!(matches!(
+            rom.get_mapper(),
+            Err(RomError::InconsistentSizes {
+                prg_rom: 0x4000,
+                ..
+            })
+        ));
+
+        // 8KB of PRG ROM, less than the fixed banks of these mappers
+        for &mapper_id in &[1u8, 2, 4, 21] {
+            let mut rom = NESTEST[..16].to_vec();
+            rom[4] = 13 << 2; // 2^13 * 1 bytes
+            rom[6] = mapper_id << 4;
+            rom[7] = (mapper_id & 0xF0) | 0x08;
+            rom[9] = 0x0F;
+            rom.extend_from_slice(&NESTEST[16..0x2010]);
+            rom.extend_from_slice(&NESTEST[0x4010..0x6010]);
+            let mut rom = RomFile::from_bytes(&rom).unwrap();
+            assert!(matches!(
+                rom.get_mapper(),
+                Err(RomError::InconsistentSizes {
+                    prg_rom: 0x2000,
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn rom_file_nes2_sizes() {
+        let mut rom = NESTEST[..16].to_vec();
+        rom[5] = 0x07; // 2^1 * 7 bytes of CHR ROM
+        rom[6] = 0x42; // MMC3 with a battery
+        rom[7] = 0x08;
+        rom[9] = 0xF0;
+        rom[10] = 0x50; // 2KB of PRG NVRAM
+        rom.extend_from_slice(&NESTEST[16..0x4010]);
+        rom.extend_from_slice(&NESTEST[0x4010..0x401E]);
+
+        let mut rom = RomFile::from_bytes(&rom).unwrap();
+        assert_eq!(rom.chr_data.len(), 14);
+
+        let mut mapper = rom.get_mapper().unwrap();
+        assert_eq!(mapper.read_chr(0x000E), mapper.read_chr(0x0000));
+
+        mapper.write_prg(0x6000, 0x42);
+        assert_eq!(mapper.read_prg(0x6800), 0x42);
+        assert_eq!(mapper.save_data().unwrap().len(), 0x800);
+    }
+
+    #[test]
+    fn rom_file_trainer() {
+        let mut rom = NESTEST[..16].to_vec();
+        rom[6] |= 0x04;
+        rom.extend((0..0x200).map(|i| i as u8));
+        rom.extend_from_slice(&NESTEST[16..]);
+
+        let mut rom = RomFile::from_bytes(&rom).unwrap();
+        assert_eq!(rom.pgr_data.as_ref(), &NESTEST[16..0x4010]);
+
+        let mapper = rom.get_mapper().unwrap();
+        assert_eq!(mapper.read_prg(0x6FFF), 0x00);
+        assert_eq!(mapper.read_prg(0x7000), 0x00);
+        assert_eq!(mapper.read_prg(0x7001), 0x01);
+        assert_eq!(mapper.read_prg(0x71FF), 0xFF);
+
+        // 2KB of PRG RAM are mirrored, the trainer goes where $7000 lands
+        let mut rom = NESTEST[..16].to_vec();
+        rom[6] |= 0x04;
+        rom[7] |= 0x08;
+        rom[10] = 0x05;
+        rom.extend((0..0x200).map(|i| i as u8));
+        rom.extend_from_slice(&NESTEST[16..]);
+
+        let mut rom = RomFile::from_bytes(&rom).unwrap();
+        let mapper = rom.get_mapper().unwrap();
+        assert_eq!(mapper.read_prg(0x7001), 0x01);
+        assert_eq!(mapper.read_prg(0x71FF), 0xFF);
+        assert_eq!(mapper.read_prg(0x7801), 0x01);
     }
 }
@@ -1,3 +1,5 @@
+use crate::rom::mapper::Mirroring;
+
 #[derive(Debug, Copy, Clone)]
 pub struct RomHeader {
     pub nes: [u8; 4],
@@ -8,6 +10,31 @@ pub struct RomHeader {
     pub prg_ram_size: u8,
     pub flags9: u8,
     pub flags10: u8,
+    // The bytes below are only meaningful in NES 2.0 headers
+    pub chr_ram_size: u8,
+    pub timing: u8,
+    pub system_type: u8,
+    pub misc_roms: u8,
+    pub expansion_device: u8,
+}
+
+/// CPU/PPU timing the game was made for
+/// https://wiki.nesdev.com/w/index.php/NES_2.0#CPU.2FPPU_Timing
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+/// Vs. System hardware, `ppu` is the PPU model (0 for the RP2C03B) and `hardware` tells the
+/// Unisystem and Dualsystem variants apart
+/// https://wiki.nesdev.com/w/index.php/NES_2.0#Vs._System_Type
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VsSystem {
+    pub ppu: u8,
+    pub hardware: u8,
 }
 
 /// https://wiki.nesdev.com/w/index.php/INES
@@ -18,7 +45,7 @@ impl RomHeader {
         let mut nes: [u8; 4] = Default::default();
         nes.copy_from_slice(&data[0..4]);
 
-        RomHeader {
+        let mut header = RomHeader {
             nes,
             prg_rom_size: data[4],
             chr_rom_size: data[5],
@@ -27,11 +54,186 @@ impl RomHeader {
             prg_ram_size: data[8],
             flags9: data[9],
             flags10: data[10],
+            chr_ram_size: data[11],
+            timing: data[12],
+            system_type: data[13],
+            misc_roms: data[14],
+            expansion_device: data[15],
+        };
+
+        // Old tools wrote things like "DiskDude!" over bytes 7-15, which would otherwise give
+        // these dumps a bogus mapper number
+        // https://wiki.nesdev.com/w/index.php/INES#Variant_comparison
+        let archaic = (header.flags7 & 0x0C) == 0x04;
+        if !header.is_nes2() && (archaic || data[12..16].iter().any(|&byte| byte != 0)) {
+            header.flags7 = 0;
+            header.prg_ram_size = 0;
+            header.flags9 = 0;
+            header.flags10 = 0;
+            header.chr_ram_size = 0;
+            header.timing = 0;
+            header.system_type = 0;
+            header.misc_roms = 0;
+            header.expansion_device = 0;
+        }
+
+        header
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/NES_2.0#Identification
+    pub fn is_nes2(&self) -> bool {
+        (self.flags7 & 0x0C) == 0x08
+    }
+
+    /// 8-bit in iNES headers, NES 2.0 headers add 4 more bits in byte 8
+    pub fn get_mapper_id(&self) -> u16 {
+        let id = ((self.flags6 >> 4) | (self.flags7 & 0xF0)) as u16;
+        if self.is_nes2() {
+            id | ((self.prg_ram_size & 0x0F) as u16) << 8
+        } else {
+            id
+        }
+    }
+
+    /// Variant of the mapper, only stored in NES 2.0 headers. 0 when unknown.
+    /// https://wiki.nesdev.com/w/index.php/NES_2.0_submappers
+    pub fn get_submapper_id(&self) -> u8 {
+        if self.is_nes2() {
+            self.prg_ram_size >> 4
+        } else {
+            0
+        }
+    }
+
+    /// Size of the PRG ROM in bytes
+    /// https://wiki.nesdev.com/w/index.php/NES_2.0#PRG-ROM_Area
+    pub fn prg_rom_bytes(&self) -> usize {
+        rom_bytes(
+            self.prg_rom_size,
+            self.nes2_bits(self.flags9 & 0x0F),
+            0x4000,
+        )
+    }
+
+    /// Size of the CHR ROM in bytes
+    /// https://wiki.nesdev.com/w/index.php/NES_2.0#CHR-ROM_Area
+    pub fn chr_rom_bytes(&self) -> usize {
+        rom_bytes(self.chr_rom_size, self.nes2_bits(self.flags9 >> 4), 0x2000)
+    }
+
+    /// Whether the cartridge has CHR ROM, otherwise the PPU pattern tables are CHR RAM
+    pub fn has_chr_rom(&self) -> bool {
+        self.chr_rom_bytes() != 0
+    }
+
+    /// Size of the volatile PRG RAM in bytes. iNES headers only have byte 8, 0 meaning 8KB.
+    /// https://wiki.nesdev.com/w/index.php/NES_2.0#PRG-.28NV.29RAM.2FEEPROM
+    pub fn prg_ram_bytes(&self) -> usize {
+        if self.is_nes2() {
+            ram_bytes(self.flags10 & 0x0F)
+        } else if self.has_battery() {
+            0
+        } else {
+            self.prg_ram_size.max(1) as usize * 0x2000
+        }
+    }
+
+    /// Size of the battery-backed PRG RAM in bytes
+    pub fn prg_nvram_bytes(&self) -> usize {
+        if self.is_nes2() {
+            ram_bytes(self.flags10 >> 4)
+        } else if self.has_battery() {
+            self.prg_ram_size.max(1) as usize * 0x2000
+        } else {
+            0
+        }
+    }
+
+    /// Size of the volatile CHR RAM in bytes. iNES headers imply 8KB without CHR ROM.
+    /// https://wiki.nesdev.com/w/index.php/NES_2.0#CHR-.28NV.29RAM
+    pub fn chr_ram_bytes(&self) -> usize {
+        if self.is_nes2() {
+            ram_bytes(self.chr_ram_size & 0x0F)
+        } else if self.chr_rom_size == 0 {
+            0x2000
+        } else {
+            0
+        }
+    }
+
+    /// Size of the battery-backed CHR RAM in bytes
+    pub fn chr_nvram_bytes(&self) -> usize {
+        ram_bytes(self.nes2_bits(self.chr_ram_size >> 4))
+    }
+
+    /// https://wiki.nesdev.com/w/index.php/NES_2.0#CPU.2FPPU_Timing
+    pub fn get_timing(&self) -> Timing {
+        let timing = if self.is_nes2() {
+            self.timing & 0x03
+        } else {
+            self.flags9 & 0x01
+        };
+
+        match timing {
+            0 => Timing::Ntsc,
+            1 => Timing::Pal,
+            2 => Timing::MultiRegion,
+            _ => Timing::Dendy,
+        }
+    }
+
+    /// Vs. System hardware the game runs on, `None` for home consoles. iNES headers only flag
+    /// the Vs. System in bit 0 of flags 7.
+    /// https://wiki.nesdev.com/w/index.php/NES_2.0#Vs._System_Type
+    pub fn get_vs_system(&self) -> Option<VsSystem> {
+        if (self.flags7 & 0x03) != 0x01 {
+            return None;
+        }
+
+        let system_type = self.nes2_bits(self.system_type);
+        Some(VsSystem {
+            ppu: system_type & 0x0F,
+            hardware: system_type >> 4,
+        })
+    }
+
+    /// Input device plugged in by default, 0 when unspecified and 1 for standard controllers
+    /// https://wiki.nesdev.com/w/index.php/NES_2.0#Default_Expansion_Device
+    pub fn get_expansion_device(&self) -> u8 {
+        self.nes2_bits(self.expansion_device & 0x3F)
+    }
+
+    /// Fields of the NES 2.0 extension, which are 0 in iNES headers
+    fn nes2_bits(&self, value: u8) -> u8 {
+        if self.is_nes2() {
+            value
+        } else {
+            0
         }
     }
 
-    pub fn get_mapper_id(&self) -> u8 {
-        (self.flags6 >> 4) | (self.flags7 & 0xF0)
+    /// Whether the cartridge keeps its PRG RAM powered with a battery
+    /// https://wiki.nesdev.com/w/index.php/INES#Flags_6
+    pub fn has_battery(&self) -> bool {
+        (self.flags6 & 0x02) != 0
+    }
+
+    /// Whether 512 bytes of trainer, meant for $7000-$71FF, come before the PRG ROM
+    /// https://wiki.nesdev.com/w/index.php/INES#Trainer
+    pub fn has_trainer(&self) -> bool {
+        (self.flags6 & 0x04) != 0
+    }
+
+    /// Hard-wired nametable arrangement, used when the mapper doesn't control it
+    /// https://wiki.nesdev.com/w/index.php/INES#Flags_6
+    pub fn get_mirroring(&self) -> Mirroring {
+        if (self.flags6 & 0x08) != 0 {
+            Mirroring::FourScreen
+        } else if (self.flags6 & 0x01) != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        }
     }
 
     pub fn is_valid(&self) -> bool {
@@ -39,3 +241,88 @@ impl RomHeader {
         self.nes == *MAGIC_STRING
     }
 }
+
+/// ROM sizes are counted in units, or written as 2^E * (MM * 2 + 1) bytes (EEEE EEMM) when the
+/// most significant nibble is $F
+fn rom_bytes(lsb: u8, msb: u8, unit: usize) -> usize {
+    if msb == 0x0F {
+        let multiplier = (lsb & 0x03) as usize * 2 + 1;
+        1usize
+            .checked_shl((lsb >> 2) as u32)
+            .map_or(usize::MAX, |size| size.saturating_mul(multiplier))
+    } else {
+        ((msb as usize) << 8 | lsb as usize) * unit
+    }
+}
+
+/// RAM sizes are written as a shift count, 64 << N bytes, 0 meaning none
+fn ram_bytes(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RomHeader;
+    use super::Timing;
+    use super::VsSystem;
+
+    #[test]
+    fn nes2_header() {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = 0x02;
+        header[5] = 0x07; // 2^1 * 7 bytes
+        header[6] = 0x32;
+        header[7] = 0x19;
+        header[8] = 0x21;
+        header[9] = 0xF1;
+        header[10] = 0x70;
+        header[11] = 0x07;
+        header[12] = 0x01;
+        header[13] = 0x12;
+        header[15] = 0x01;
+        let header = RomHeader::new(&header);
+
+        assert!(header.is_nes2());
+        assert_eq!(header.get_mapper_id(), 0x113);
+        assert_eq!(header.get_submapper_id(), 2);
+        assert_eq!(header.prg_rom_bytes(), 0x102 * 0x4000);
+        assert_eq!(header.chr_rom_bytes(), 14);
+        assert_eq!(header.prg_ram_bytes(), 0);
+        assert_eq!(header.prg_nvram_bytes(), 0x2000);
+        assert_eq!(header.chr_ram_bytes(), 0x2000);
+        assert_eq!(header.chr_nvram_bytes(), 0);
+        assert_eq!(header.get_timing(), Timing::Pal);
+        assert_eq!(
+            header.get_vs_system(),
+            Some(VsSystem {
+                ppu: 2,
+                hardware: 1
+            })
+        );
+        assert_eq!(header.get_expansion_device(), 1);
+    }
+
+    #[test]
+    fn disk_dude_header() {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = 2;
+        header[5] = 1;
+        header[6] = 0x41;
+        header[7..16].copy_from_slice(b"DiskDude!");
+        let header = RomHeader::new(&header);
+
+        assert!(!header.is_nes2());
+        assert_eq!(header.get_mapper_id(), 4);
+        assert_eq!(header.prg_rom_bytes(), 0x8000);
+        assert_eq!(header.chr_rom_bytes(), 0x2000);
+        assert_eq!(header.prg_ram_bytes(), 0x2000);
+        assert_eq!(header.get_timing(), Timing::Ntsc);
+        assert_eq!(header.get_vs_system(), None);
+    }
+}
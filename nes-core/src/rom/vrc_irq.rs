@@ -0,0 +1,124 @@
+/// CPU cycles per scanline multiplied by 3, the prescaler counts down by 3 every CPU cycle.
+const PRESCALER_PERIOD: i16 = 341;
+
+/// IRQ counter shared by the Konami VRC4, VRC6 and VRC7. It counts CPU cycles, either directly
+/// or through a prescaler that approximates scanlines.
+/// https://wiki.nesdev.com/w/index.php/VRC_IRQ
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    enable_after_ack: bool,
+    /// Cycle mode (1) or scanline mode (0)
+    cycle_mode: bool,
+    pub pending: bool,
+}
+
+impl Default for VrcIrq {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VrcIrq {
+    pub fn new() -> Self {
+        Self {
+            latch: 0,
+            counter: 0,
+            prescaler: PRESCALER_PERIOD,
+            enabled: false,
+            enable_after_ack: false,
+            cycle_mode: false,
+            pending: false,
+        }
+    }
+
+    pub fn write_latch(&mut self, value: u8) {
+        self.latch = value;
+    }
+
+    /// VRC4 splits the latch into two nibble registers
+    pub fn write_latch_low(&mut self, value: u8) {
+        self.latch = (self.latch & 0xF0) | (value & 0x0F);
+    }
+
+    pub fn write_latch_high(&mut self, value: u8) {
+        self.latch = (self.latch & 0x0F) | ((value & 0x0F) << 4);
+    }
+
+    /// IRQ control ---- -MEA
+    pub fn write_control(&mut self, value: u8) {
+        self.enable_after_ack = (value & 0x01) != 0;
+        self.enabled = (value & 0x02) != 0;
+        self.cycle_mode = (value & 0x04) != 0;
+        self.pending = false;
+
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_PERIOD;
+        }
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    /// Clocked on every CPU cycle.
+    pub fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.cycle_mode {
+            self.clock_counter();
+        } else {
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += PRESCALER_PERIOD;
+                self.clock_counter();
+            }
+        }
+    }
+
+    fn clock_counter(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VrcIrq;
+
+    #[test]
+    fn cycle_and_scanline_modes() {
+        let mut irq = VrcIrq::new();
+        irq.write_latch(0xFD);
+        irq.write_control(0x07); // Cycle mode, enabled, enabled after acknowledge
+
+        for _ in 0..2 {
+            irq.clock();
+        }
+        assert!(!irq.pending);
+        irq.clock();
+        assert!(irq.pending);
+
+        irq.acknowledge();
+        assert!(!irq.pending);
+
+        // Three scanlines of 113.667 CPU cycles
+        irq.write_control(0x02);
+        for _ in 0..340 {
+            irq.clock();
+        }
+        assert!(!irq.pending);
+        irq.clock();
+        assert!(irq.pending);
+    }
+}
@@ -5,6 +5,7 @@ use crate::palette;
 use crate::rom::rom_file::RomFile;
 use regex::Regex;
 use std::fs::File;
+use std::io::Cursor;
 use std::path::Path;
 use std::u16;
 
@@ -13,10 +14,10 @@ const LOG_REGEX_PATTERN: &str = r"([0-9A-F]{4})  ([0-9A-F]{2}) ([0-9A-F]{2}|\s{2
 const ROM_NESTEST: &[u8] = include_bytes!("../test/nestest.nes");
 
 fn nes_with_rom(rom_bytes: &[u8], start_addr: u16) -> NesConsole {
-    let mut rom = RomFile::from_bytes(rom_bytes);
+    let rom = RomFile::from_bytes(rom_bytes).unwrap();
     let nes = NesConsole::new();
 
-    nes.bus.borrow_mut().connect_cartridge(&mut rom);
+    nes.bus.borrow_mut().connect_cartridge(rom).unwrap();
 
     {
         let mut cpu = nes.cpu.borrow_mut();
@@ -25,6 +26,17 @@ fn nes_with_rom(rom_bytes: &[u8], start_addr: u16) -> NesConsole {
     nes
 }
 
+#[test]
+fn save_data_needs_battery() {
+    let nes = nes_with_rom(ROM_NESTEST, 0xC000);
+    assert!(nes.get_save_data().is_none());
+
+    let mut rom_bytes = ROM_NESTEST.to_vec();
+    rom_bytes[6] |= 0x02; // Battery
+    let nes = nes_with_rom(&rom_bytes, 0xC000);
+    assert_eq!(nes.get_save_data().map(|data| data.len()), Some(0x2000));
+}
+
 #[test]
 fn cpu_instructions() {
     let regex = Regex::new(LOG_REGEX_PATTERN).unwrap();
@@ -114,10 +126,10 @@ fn ppu_timings() {
 #[ignore]
 fn screenshot() {
     const ROM_DONKEY_KONG: &[u8] = include_bytes!("../test/Donkey Kong (World) (Rev A).nes");
-    let mut rom = RomFile::from_bytes(ROM_DONKEY_KONG);
+    let rom = RomFile::from_bytes(ROM_DONKEY_KONG).unwrap();
     let mut nes = NesConsole::new();
 
-    nes.bus.borrow_mut().connect_cartridge(&mut rom);
+    nes.bus.borrow_mut().connect_cartridge(rom).unwrap();
     nes.reset();
 
     for _ in 0..15 {
@@ -146,11 +158,11 @@ fn screenshot() {
 fn gif() {
     // const ROM_DONKEY_KONG: &[u8] = include_bytes!("../test/Donkey Kong (World) (Rev A).nes");
     const ROM_DONKEY_KONG: &[u8] = include_bytes!("../../roms/Testing/NEStress.NES");
-    let mut rom = RomFile::from_bytes(ROM_DONKEY_KONG);
+    let rom = RomFile::from_bytes(ROM_DONKEY_KONG).unwrap();
     let mut nes = NesConsole::new();
     let mut encoder = nes.get_gif_encoder(Path::new("test.gif"));
 
-    nes.bus.borrow_mut().connect_cartridge(&mut rom);
+    nes.bus.borrow_mut().connect_cartridge(rom).unwrap();
     nes.reset();
 
     for _ in 0..250 {
@@ -159,3 +171,61 @@ fn gif() {
         nes.frame_to_gif(&mut encoder);
     }
 }
+
+#[test]
+#[ignore]
+fn wav() {
+    const ROM_DONKEY_KONG: &[u8] = include_bytes!("../test/Donkey Kong (World) (Rev A).nes");
+    let rom = RomFile::from_bytes(ROM_DONKEY_KONG).unwrap();
+    let mut nes = NesConsole::new();
+    let mut buf = vec![0f32; 4096];
+
+    nes.bus.borrow_mut().connect_cartridge(rom).unwrap();
+    nes.reset();
+    nes.start_wav_recording(Path::new("test.wav")).unwrap();
+
+    for _ in 0..600 {
+        nes.render_full_frame();
+        nes.get_audio_output_f32(&mut buf);
+    }
+
+    nes.stop_wav_recording().unwrap();
+}
+
+#[test]
+fn wav_recording_twice() {
+    let mut nes = NesConsole::new();
+
+    nes.start_wav_recording_to(Vec::new()).unwrap();
+    assert!(nes.start_wav_recording_to(Vec::new()).is_err());
+    assert!(nes.is_recording_wav());
+
+    nes.stop_wav_recording().unwrap();
+    assert!(!nes.is_recording_wav());
+    nes.start_wav_recording_to_seekable(Cursor::new(Vec::new()))
+        .unwrap();
+}
+
+#[test]
+fn wav_recording_finished_on_drop() {
+    let path = std::env::temp_dir().join("nes-rs-wav-recording-finished-on-drop.wav");
+    {
+        let mut nes = nes_with_rom(ROM_NESTEST, 0xC000);
+        nes.reset();
+        nes.start_wav_recording(&path).unwrap();
+
+        // Nothing pulls the audio output, the recording is fed as the APU generates it
+        for _ in 0..20 {
+            nes.render_full_frame();
+        }
+    }
+
+    let wav = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let data_size = u32::from_le_bytes([wav[40], wav[41], wav[42], wav[43]]) as usize;
+    assert_eq!(data_size, wav.len() - 44);
+    assert_eq!(&wav[4..8], &(data_size as u32 + 36).to_le_bytes());
+    // A third of a second of audio, minus the samples still in the resampler
+    assert!(data_size > 14_500 * 2, "{} bytes of audio", data_size);
+}
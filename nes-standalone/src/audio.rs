@@ -0,0 +1,91 @@
+use std::env;
+
+use sdl2::audio::AudioQueue;
+use sdl2::audio::AudioSpecDesired;
+
+use nes_core::console::NesConsole;
+
+const SAMPLE_RATE: i32 = 44_100;
+/// Amount of audio kept queued, in samples. Lower values reduce latency but crackle sooner.
+const TARGET_QUEUED_SAMPLES: u32 = (SAMPLE_RATE / 20) as u32; // 50ms
+/// Maximum deviation from the nominal sample rate applied by the dynamic rate control.
+const MAX_RATE_DELTA: f64 = 0.005;
+
+/// Plays the console audio through an SDL2 audio queue. Emulation is paced by the queue: a new
+/// frame is only needed once the queued audio drops below the target, and the sample rate of the
+/// console is nudged so the queue hovers around that target instead of slowly draining or
+/// piling up (dynamic rate control).
+/// https://docs.libretro.com/development/cores/dynamic-rate-control/
+pub struct AudioOutput {
+    queue: AudioQueue<f32>,
+    buffer: Vec<f32>,
+    dummy: bool,
+}
+
+impl AudioOutput {
+    /// Opens the default audio device, falling back to SDL's dummy driver when there is none.
+    pub fn new() -> Self {
+        let sdl = sdl2::init().unwrap();
+
+        let queue = open_queue(&sdl).unwrap_or_else(|e| {
+            println!("Failed to open audio device ({}), audio disabled", e);
+            env::set_var("SDL_AUDIODRIVER", "dummy");
+            open_queue(&sdl).unwrap()
+        });
+
+        let dummy = queue.subsystem().current_audio_driver() == "dummy";
+        queue.resume();
+
+        Self {
+            queue,
+            buffer: Vec::new(),
+            dummy,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.queue.spec().freq as u32
+    }
+
+    /// Whether the audio is going nowhere, in which case it can't be used to pace the emulation.
+    pub fn is_dummy(&self) -> bool {
+        self.dummy
+    }
+
+    fn queued_samples(&self) -> u32 {
+        self.queue.size() / std::mem::size_of::<f32>() as u32
+    }
+
+    /// Whether another frame should be emulated to keep the audio queue fed.
+    pub fn needs_samples(&self) -> bool {
+        self.queued_samples() < TARGET_QUEUED_SAMPLES
+    }
+
+    /// Moves the audio generated by the console into the queue.
+    pub fn push_samples(&mut self, nes: &mut NesConsole) {
+        self.buffer.resize(nes.audio_samples_available(), 0.0);
+        let count = nes.get_audio_output_f32(&mut self.buffer);
+
+        if self.dummy {
+            return;
+        }
+
+        self.queue.queue(&self.buffer[..count]);
+
+        let fill = self.queued_samples() as f64 / (2 * TARGET_QUEUED_SAMPLES) as f64;
+        let ratio = 1.0 + MAX_RATE_DELTA * (1.0 - 2.0 * fill.min(1.0));
+        let sample_rate = self.queue.spec().freq as f64 * ratio;
+        nes.set_audio_sample_rate(sample_rate.round() as u32);
+    }
+}
+
+fn open_queue(sdl: &sdl2::Sdl) -> Result<AudioQueue<f32>, String> {
+    let audio_subsystem = sdl.audio()?;
+    let desired_spec = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE),
+        channels: Some(1),
+        samples: Some(1024),
+    };
+
+    audio_subsystem.open_queue(None, &desired_spec)
+}
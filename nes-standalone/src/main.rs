@@ -4,13 +4,17 @@ extern crate gl;
 extern crate nes_core;
 extern crate nfd;
 extern crate png;
+extern crate sdl2;
 extern crate stopwatch;
 extern crate winit;
 
+mod audio;
+
 use stopwatch::Stopwatch;
 
 use std::env;
 use std::path::Path;
+use std::process;
 
 use pixels::wgpu::TextureFormat;
 use pixels::PixelsBuilder;
@@ -29,15 +33,22 @@ use winit_input_helper::WinitInputHelper;
 
 use nfd::Response;
 
+use sdl2::messagebox::show_simple_message_box;
+use sdl2::messagebox::MessageBoxFlag;
+
 use nes_core::console::NesConsole;
 use nes_core::console::NES_HEIGHT;
 use nes_core::console::NES_WIDTH;
 use nes_core::input::joypad::Joypad;
 use nes_core::input::joypad::JoypadDataLine;
 use nes_core::input::InputType;
+use nes_core::rom::rom_file::RomError;
 use nes_core::rom::rom_file::RomFile;
 
+use audio::AudioOutput;
+
 const TARGET_FRAMERATE: i64 = 60;
+const SAVE_INTERVAL_MS: i64 = 30_000;
 const HIGH_QUALITY: bool = false;
 
 const KEYMAPS: &[(VirtualKeyCode, JoypadDataLine)] = &[
@@ -69,12 +80,17 @@ fn main() -> ! {
     }
 }
 
-fn load_nes(rom_path: &Path) -> NesConsole {
+fn load_nes(rom_path: &Path) -> Result<NesConsole, RomError> {
     println!("Loading ROM from {}", rom_path.display());
-    let rom = RomFile::from_file(rom_path);
+    let rom = RomFile::from_file(rom_path)?;
     let mut nes = NesConsole::new();
 
-    nes.bus.borrow_mut().connect_cartridge(rom);
+    nes.bus.borrow_mut().connect_cartridge(rom)?;
+
+    let save_path = RomFile::save_path(rom_path);
+    if let Err(e) = nes.load_save_file(&save_path) {
+        println!("Failed to load {}: {}", save_path.display(), e);
+    }
 
     {
         let mut bus = nes.bus.borrow_mut();
@@ -83,11 +99,38 @@ fn load_nes(rom_path: &Path) -> NesConsole {
     }
 
     nes.reset();
-    nes
+    Ok(nes)
+}
+
+/// Writes the battery-backed memory to the save file, if it changed since the last write
+fn flush_save(nes: &NesConsole, save_path: &Path, last_save: &mut Option<Vec<u8>>) {
+    let data = nes.get_save_data();
+    if data.is_none() || data == *last_save {
+        return;
+    }
+
+    match nes.flush_save_file(save_path) {
+        Ok(()) => println!("Saved to {}", save_path.display()),
+        Err(e) => println!("Failed to save {}: {}", save_path.display(), e),
+    }
+    *last_save = data;
+}
+
+/// Release builds have no console on Windows, so errors go to a dialog as well
+fn show_error(message: &str) {
+    eprintln!("{}", message);
+    if let Err(e) = show_simple_message_box(MessageBoxFlag::ERROR, "NES Emulator", message, None) {
+        eprintln!("Failed to show the error dialog: {}", e);
+    }
 }
 
 fn start(rom_path: &Path) -> ! {
-    let mut nes = load_nes(rom_path);
+    let mut nes = load_nes(rom_path).unwrap_or_else(|e| {
+        show_error(&format!("Failed to load {}: {}", rom_path.display(), e));
+        process::exit(1);
+    });
+    let save_path = RomFile::save_path(rom_path);
+    let mut last_save = nes.get_save_data();
 
     // Generate output buffers
     let mut output_buffer = vec![0; (NES_WIDTH * NES_HEIGHT) as usize];
@@ -122,8 +165,12 @@ fn start(rom_path: &Path) -> ! {
             .unwrap()
     };
 
+    let mut audio = AudioOutput::new();
+    nes.set_audio_sample_rate(audio.sample_rate());
+
     let mut sw = Stopwatch::start_new();
     let mut sync = Stopwatch::start_new();
+    let mut save_sw = Stopwatch::start_new();
     let mut frames = 0;
     let mut rendered_frames = 0;
 
@@ -167,6 +214,7 @@ fn start(rom_path: &Path) -> ! {
                     pixels.resize_surface(size.width, size.height);
                 }
             }
+            Event::LoopDestroyed => flush_save(&nes, &save_path, &mut last_save),
             _ => {}
         }
 
@@ -212,10 +260,23 @@ fn start(rom_path: &Path) -> ! {
             rendered_frames = 0;
         }
 
-        if sync.elapsed_ms() >= (1000 / TARGET_FRAMERATE) {
+        if save_sw.elapsed_ms() > SAVE_INTERVAL_MS {
+            save_sw.restart();
+            flush_save(&nes, &save_path, &mut last_save);
+        }
+
+        // Without an audio device, fall back to pacing frames with the clock
+        let run_frame = if audio.is_dummy() {
+            sync.elapsed_ms() >= (1000 / TARGET_FRAMERATE)
+        } else {
+            audio.needs_samples()
+        };
+
+        if run_frame {
             sync.restart();
             frames += 1;
             nes.render_full_frame();
+            audio.push_samples(&mut nes);
             window.request_redraw();
         }
     });
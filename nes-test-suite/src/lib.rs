@@ -6,10 +6,10 @@ mod test {
     use nes_core::rom::rom_file::RomFile;
 
     fn nes_with_rom(rom_bytes: &[u8]) -> NesConsole {
-        let mut rom = RomFile::from_bytes(rom_bytes);
+        let rom = RomFile::from_bytes(rom_bytes).unwrap();
         let mut nes = NesConsole::new();
 
-        nes.bus.borrow_mut().connect_cartridge(&mut rom);
+        nes.bus.borrow_mut().connect_cartridge(rom).unwrap();
         nes.reset();
         nes
     }
@@ -13,6 +13,7 @@ use wasm_bindgen::prelude::*;
 use wasm_bindgen::Clamped;
 use wasm_bindgen::JsCast;
 
+use nes_core::apu::AudioChannel;
 use nes_core::console::NesConsole;
 use nes_core::console::NES_HEIGHT;
 use nes_core::console::NES_WIDTH;
@@ -35,6 +36,19 @@ pub enum ControllerKeys {
     RIGHT = 128,
 }
 
+#[wasm_bindgen]
+pub enum AudioChannels {
+    PULSE1 = 0,
+    PULSE2 = 1,
+    TRIANGLE = 2,
+    NOISE = 3,
+    DMC = 4,
+    EXPANSION = 5,
+}
+
+/// Number of samples returned by `get_channel_waveform`.
+const WAVEFORM_SIZE: usize = 1024;
+
 #[wasm_bindgen]
 pub struct NesWebContext {
     nes: NesConsole,
@@ -63,10 +77,24 @@ impl NesWebContext {
         self.nes.reset();
     }
 
-    pub fn insert_cartridge(&mut self, rom_bytes: Vec<u8>) {
-        let rom = RomFile::from_bytes(&rom_bytes);
+    /// Throws when the ROM can't be loaded, keeping the current cartridge
+    pub fn insert_cartridge(&mut self, rom_bytes: Vec<u8>) -> Result<(), JsValue> {
+        let rom = RomFile::from_bytes(&rom_bytes).map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        self.nes
+            .bus
+            .borrow_mut()
+            .connect_cartridge(rom)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Battery-backed memory of the cartridge, `undefined` when it has no battery
+    pub fn get_save_data(&self) -> Option<Vec<u8>> {
+        self.nes.get_save_data()
+    }
 
-        self.nes.bus.borrow_mut().connect_cartridge(rom);
+    pub fn set_save_data(&mut self, data: Vec<u8>) {
+        self.nes.set_save_data(&data);
     }
 
     pub fn attach_joypad(&mut self, input: u8) {
@@ -169,6 +197,36 @@ impl NesWebContext {
         output_buffer
     }
 
+    pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        self.nes.set_audio_sample_rate(sample_rate);
+    }
+
+    pub fn get_audio_output_f32(&self) -> Vec<f32> {
+        let mut output_buffer = vec![0.0; self.nes.audio_samples_available()];
+        self.nes.get_audio_output_f32(&mut output_buffer);
+        output_buffer
+    }
+
+    pub fn set_channel_muted(&mut self, channel: u8, muted: bool) {
+        if let Some(channel) = AudioChannel::ALL.get(channel as usize) {
+            self.nes.set_audio_channel_muted(*channel, muted);
+        }
+    }
+
+    pub fn set_channel_soloed(&mut self, channel: u8, soloed: bool) {
+        if let Some(channel) = AudioChannel::ALL.get(channel as usize) {
+            self.nes.set_audio_channel_soloed(*channel, soloed);
+        }
+    }
+
+    pub fn get_channel_waveform(&self, channel: u8) -> Vec<f32> {
+        let mut output_buffer = vec![0.0; WAVEFORM_SIZE];
+        if let Some(channel) = AudioChannel::ALL.get(channel as usize) {
+            self.nes.get_audio_channel_waveform(*channel, &mut output_buffer);
+        }
+        output_buffer
+    }
+
     pub fn setup_canvas(&mut self, canvas: &web_sys::HtmlCanvasElement) {
         canvas.set_width(NES_WIDTH);
         canvas.set_height(NES_HEIGHT);
@@ -94,15 +94,55 @@ context.setup_canvas(canvas);
 context.attach_joypad(0);
 // context.attach_zapper_gun(1);
 
+let saveKey = null;
+
+// Converted in chunks, spreading a whole 64KB save into fromCharCode's arguments can exceed
+// the engine's argument limit
+const bytesToBase64 = (bytes) => {
+  let binary = "";
+  for (let i = 0; i < bytes.length; i += 0x2000) {
+    binary += String.fromCharCode(...bytes.subarray(i, i + 0x2000));
+  }
+  return btoa(binary);
+};
+
+const base64ToBytes = (base64) =>
+  Uint8Array.from(atob(base64), (c) => c.charCodeAt(0));
+
+const storeSave = () => {
+  const data = saveKey && context.get_save_data();
+  if (data) {
+    localStorage.setItem(saveKey, bytesToBase64(data));
+  }
+};
+
 romInput.addEventListener("input", async (evt) => {
+  storeSave();
+
   const file = evt.target.files[0];
   const buffer = await file.arrayBuffer();
   const arr = new Uint8Array(buffer);
-  context.insert_cartridge(arr);
+  try {
+    context.insert_cartridge(arr);
+  } catch (e) {
+    console.error(`Failed to load ${file.name}:`, e);
+    alert(`Failed to load ${file.name}: ${e}`);
+    return;
+  }
+
+  saveKey = `save:${file.name}`;
+  const save = localStorage.getItem(saveKey);
+  if (save) {
+    context.set_save_data(base64ToBytes(save));
+  }
+
   context.reset();
   console.log(`${file.name} loaded`);
 });
 
+setInterval(storeSave, 10000);
+window.addEventListener("beforeunload", storeSave);
+
 console.log("Input 0:", context.get_input_type(0));
 console.log("Input 1:", context.get_input_type(1));
 